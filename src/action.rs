@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::{self, Display};
+use std::io::{self, prelude::*};
+use std::path::PathBuf;
+use std::process::{Child, Command as CliCommand, Stdio};
+
+use directories::BaseDirs;
+use serde::de::{self, Deserialize, Deserializer};
+use serde_derive::Deserialize;
+
+static DEFAULT_SHELL: &str = "sh";
+pub static EMPTY_SHELL_ERROR: &str = "`shell` must name a shell, e.g. `bash`";
+/// Time for the window of spacerun to close before typing into the one below.
+static TYPE_DELAY: &str = "0.3";
+
+/**
+ * What a command does when it is selected
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Run `cmd` with `shell -c`, which a plain `cmd` of a command does
+    Shell { cmd: String, shell: Option<String> },
+    /// Run a program with its arguments, without a shell in between
+    Exec(Vec<String>),
+    /// Open a URL or path with `xdg-open`
+    Open(String),
+    /// Copy text to the clipboard
+    Clip(String),
+    /// Type text into the window that was focused before spacerun
+    Type(String),
+    Notify { summary: String, body: Option<String> },
+}
+
+/**
+ * Where and how the action of a command runs
+ *
+ * Menus pass their settings down to their children, see `inherit`.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunSettings {
+    /// Working directory, spacerun's own if missing
+    pub cwd: Option<PathBuf>,
+    /// Variables set on top of spacerun's environment
+    pub env: BTreeMap<String, String>,
+    /// The shell running `cmd`s, `sh` if missing
+    pub shell: Option<String>,
+}
+
+/**
+ * What the process of an action reads from and writes to
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Streams {
+    /// The stdin, stdout and stderr of spacerun
+    Inherited,
+    /// No stdin and stdout, e.g. not to keep a dmenu pipeline waiting
+    Detached,
+    /// No stdin, stdout and stderr are piped to spacerun
+    Captured,
+}
+
+/**
+ * The shape of each action in the config, e.g. `"action": {"exec": ["ls", "-l"]}`
+ */
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RawAction {
+    Shell(RawShell),
+    Exec(Vec<String>),
+    Open(String),
+    Clip(String),
+    Type(String),
+    Notify(RawNotify),
+}
+
+#[derive(Deserialize)]
+struct RawShell {
+    cmd: String,
+    /// The shell to run `cmd` with instead of `sh`
+    shell: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawNotify {
+    summary: String,
+    body: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for Action {
+    fn deserialize<D>(deserializer: D) -> Result<Action, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let action = match RawAction::deserialize(deserializer)? {
+            RawAction::Shell(RawShell { shell: Some(shell), .. }) if shell.trim().is_empty() => {
+                return Err(de::Error::custom(EMPTY_SHELL_ERROR))
+            }
+            RawAction::Shell(RawShell { cmd, shell }) => Action::Shell { cmd, shell },
+            RawAction::Exec(argv) => {
+                if argv.is_empty() || argv[0].is_empty() {
+                    return Err(de::Error::custom("`exec` needs at least the program to run"));
+                }
+                Action::Exec(argv)
+            }
+            RawAction::Open(target) if target.is_empty() => {
+                return Err(de::Error::custom("`open` needs a URL or path"))
+            }
+            RawAction::Open(target) => Action::Open(target),
+            RawAction::Clip(text) => Action::Clip(text),
+            RawAction::Type(text) if text.is_empty() => {
+                return Err(de::Error::custom("`type` needs the text to type"))
+            }
+            RawAction::Type(text) => Action::Type(text),
+            RawAction::Notify(RawNotify { summary, .. }) if summary.is_empty() => {
+                return Err(de::Error::custom("`notify` needs a `summary`"))
+            }
+            RawAction::Notify(RawNotify { summary, body }) => Action::Notify { summary, body },
+        };
+        Ok(action)
+    }
+}
+
+impl Action {
+    /// Run `cmd` with `sh -c`.
+    pub fn shell(cmd: String) -> Action {
+        Action::Shell { cmd, shell: None }
+    }
+
+    /// Start the action with `settings` without waiting for it.
+    pub fn spawn(&self, settings: &RunSettings, streams: Streams) -> io::Result<Child> {
+        let mut process = self.process(settings.shell.as_deref());
+        if let Some(cwd) = &settings.cwd {
+            // Spawning would only say that some file is missing
+            if !cwd.is_dir() {
+                let message = format!("The working directory {} does not exist", cwd.display());
+                return Err(io::Error::new(io::ErrorKind::NotFound, message));
+            }
+            process.current_dir(cwd);
+        }
+        process.envs(&settings.env);
+        match streams {
+            Streams::Inherited => {}
+            Streams::Detached => {
+                process.stdin(Stdio::null()).stdout(Stdio::null());
+            }
+            Streams::Captured => {
+                process
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped());
+            }
+        }
+        let input = match self {
+            Action::Clip(text) => Some(text),
+            _ => None,
+        };
+        if input.is_some() {
+            process.stdin(Stdio::piped());
+        }
+
+        let mut child = process.spawn()?;
+        if let Some(input) = input {
+            // Closing stdin afterwards lets the clipboard tool take the text
+            let mut stdin = child.stdin.take().expect("stdin of the child is piped");
+            if let Err(err) = stdin.write_all(input.as_bytes()) {
+                // Not to leave a clipboard tool behind that never gets the text
+                let _ = child.kill();
+                let _ = child.wait();
+                let message = format!("Could not pass the text to it: {}", err);
+                return Err(io::Error::new(err.kind(), message));
+            }
+        }
+        Ok(child)
+    }
+
+    /// The process carrying out the action, running a `cmd` with `shell`.
+    fn process(&self, shell: Option<&str>) -> CliCommand {
+        let wayland = env::var_os("WAYLAND_DISPLAY").is_some();
+        match self {
+            Action::Shell { cmd, shell: own_shell } => {
+                let shell = own_shell.as_deref().or(shell).unwrap_or(DEFAULT_SHELL);
+                let mut process = CliCommand::new(shell);
+                process.arg("-c").arg(cmd);
+                process
+            }
+            Action::Exec(argv) => {
+                let mut process = CliCommand::new(&argv[0]);
+                process.args(&argv[1..]);
+                process
+            }
+            Action::Open(target) => {
+                let mut process = CliCommand::new("xdg-open");
+                process.arg(target);
+                process
+            }
+            Action::Clip(_) if wayland => CliCommand::new("wl-copy"),
+            Action::Clip(_) => {
+                let mut process = CliCommand::new("xclip");
+                process.args(["-selection", "clipboard"]);
+                process
+            }
+            Action::Type(text) if wayland => {
+                let mut process = CliCommand::new("wtype");
+                process.args(["-s", "300", "--"]).arg(text);
+                process
+            }
+            Action::Type(text) => {
+                let mut process = CliCommand::new("xdotool");
+                process
+                    .args(["sleep", TYPE_DELAY, "type", "--clearmodifiers", "--"])
+                    .arg(text);
+                process
+            }
+            Action::Notify { summary, body } => {
+                let mut process = CliCommand::new("notify-send");
+                process.arg("--").arg(summary).args(body);
+                process
+            }
+        }
+    }
+}
+
+impl RunSettings {
+    /**
+     * These settings of a command inside a menu with the `inherited` ones.
+     *
+     * Settings of the command win, variables are added to the inherited
+     * ones and a relative `cwd` is relative to the inherited one.
+     */
+    pub fn inherit(&self, inherited: &RunSettings) -> RunSettings {
+        let cwd = match (&inherited.cwd, &self.cwd) {
+            (Some(inherited_cwd), Some(cwd)) => Some(inherited_cwd.join(cwd)),
+            (inherited_cwd, cwd) => cwd.clone().or_else(|| inherited_cwd.clone()),
+        };
+        let mut env = inherited.env.clone();
+        env.extend(self.env.clone());
+        RunSettings {
+            cwd,
+            env,
+            shell: self.shell.clone().or_else(|| inherited.shell.clone()),
+        }
+    }
+
+    /// Put the settings of the same menu in the config layer `upper` on top.
+    pub fn merge(self, upper: RunSettings) -> RunSettings {
+        let mut env = self.env;
+        env.extend(upper.env);
+        RunSettings {
+            cwd: upper.cwd.or(self.cwd),
+            env,
+            shell: upper.shell.or(self.shell),
+        }
+    }
+}
+
+/// `path` with a leading `~` replaced by the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = BaseDirs::new().map(|dirs| dirs.home_dir().to_owned());
+    match (home, path.strip_prefix('~')) {
+        (Some(home), Some("")) => home,
+        (Some(home), Some(rest)) if rest.starts_with('/') => home.join(&rest[1..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Shell { cmd, shell: None } => write!(f, "{}", cmd),
+            Action::Shell {
+                cmd,
+                shell: Some(shell),
+            } => write!(f, "{} -c {}", shell, quote(cmd)),
+            Action::Exec(argv) => {
+                let argv: Vec<String> = argv.iter().map(|arg| quote(arg)).collect();
+                write!(f, "{}", argv.join(" "))
+            }
+            Action::Open(target) => write!(f, "open {}", target),
+            Action::Clip(text) => write!(f, "copy {}", quote(text)),
+            Action::Type(text) => write!(f, "type {}", quote(text)),
+            Action::Notify { summary, .. } => write!(f, "notify {}", quote(summary)),
+        }
+    }
+}
+
+/// `text` as a single word for `sh`, to show actions the way they are run.
+fn quote(text: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !text.is_empty() && text.chars().all(plain) {
+        text.to_owned()
+    } else {
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
+}
@@ -1,121 +1,202 @@
 use std::fmt::{self, Display};
+use std::ops::RangeInclusive;
 use std::str::FromStr;
 
-use conrod::backend::glium::glium::glutin::{ModifiersState, VirtualKeyCode};
 use serde::de;
+use serde_derive::Deserialize;
 
 /**
- * A pressed key
+ * A key, independent of the frontend it was pressed in
  */
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct KeyCode(VirtualKeyCode);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCode {
+    /**
+     * A letter, digit or punctuation key, by its unshifted character
+     *
+     * Other characters, e.g. `?`, are matched by the character typed. An
+     * uppercase letter is its lowercase key with Shift.
+     */
+    Char(char),
+    Space,
+    Return,
+    Tab,
+    Delete,
+    Insert,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Up,
+    Down,
+    Left,
+    Right,
+    /// A function key, `F(1)` to `F(24)`
+    F(u8),
+    /// A digit key of the numpad
+    Numpad(u8),
+    NumpadAdd,
+    NumpadSubtract,
+    NumpadMultiply,
+    NumpadDivide,
+    NumpadDecimal,
+    NumpadEnter,
+    NumpadEquals,
+    PlayPause,
+    MediaStop,
+    NextTrack,
+    PrevTrack,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+}
+
+/// Punctuation keys, which are named by their character like letters.
+static PUNCTUATION: &str = ",./;'[]-=`";
+
+/// Names of the keys without a character, the first one is used to display a key.
+static KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("SPC", KeyCode::Space),
+    ("RET", KeyCode::Return),
+    ("Return", KeyCode::Return),
+    ("TAB", KeyCode::Tab),
+    ("Tab", KeyCode::Tab),
+    ("Delete", KeyCode::Delete),
+    ("Insert", KeyCode::Insert),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PgUp", KeyCode::PageUp),
+    ("PgDn", KeyCode::PageDown),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("KPAdd", KeyCode::NumpadAdd),
+    ("KPSubtract", KeyCode::NumpadSubtract),
+    ("KPMultiply", KeyCode::NumpadMultiply),
+    ("KPDivide", KeyCode::NumpadDivide),
+    ("KPDecimal", KeyCode::NumpadDecimal),
+    ("KPEnter", KeyCode::NumpadEnter),
+    ("KPEquals", KeyCode::NumpadEquals),
+    ("PlayPause", KeyCode::PlayPause),
+    ("MediaStop", KeyCode::MediaStop),
+    ("NextTrack", KeyCode::NextTrack),
+    ("PrevTrack", KeyCode::PrevTrack),
+    ("VolumeUp", KeyCode::VolumeUp),
+    ("VolumeDown", KeyCode::VolumeDown),
+    ("Mute", KeyCode::Mute),
+];
+
+/// Keys spacerun handles itself, which cannot be bound to commands.
+static RESERVED_KEYS: &[&str] = &["ESC", "Esc", "Escape", "Backspace", "BS"];
 
 impl FromStr for KeyCode {
     type Err = ShortcutFromStrError;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
-        match value {
-            "a" => Ok(KeyCode(VirtualKeyCode::A)),
-            "b" => Ok(KeyCode(VirtualKeyCode::B)),
-            "c" => Ok(KeyCode(VirtualKeyCode::C)),
-            "d" => Ok(KeyCode(VirtualKeyCode::D)),
-            "e" => Ok(KeyCode(VirtualKeyCode::E)),
-            "f" => Ok(KeyCode(VirtualKeyCode::F)),
-            "g" => Ok(KeyCode(VirtualKeyCode::G)),
-            "h" => Ok(KeyCode(VirtualKeyCode::H)),
-            "i" => Ok(KeyCode(VirtualKeyCode::I)),
-            "j" => Ok(KeyCode(VirtualKeyCode::J)),
-            "k" => Ok(KeyCode(VirtualKeyCode::K)),
-            "l" => Ok(KeyCode(VirtualKeyCode::L)),
-            "m" => Ok(KeyCode(VirtualKeyCode::M)),
-            "n" => Ok(KeyCode(VirtualKeyCode::N)),
-            "o" => Ok(KeyCode(VirtualKeyCode::O)),
-            "p" => Ok(KeyCode(VirtualKeyCode::P)),
-            "q" => Ok(KeyCode(VirtualKeyCode::Q)),
-            "r" => Ok(KeyCode(VirtualKeyCode::R)),
-            "s" => Ok(KeyCode(VirtualKeyCode::S)),
-            "t" => Ok(KeyCode(VirtualKeyCode::T)),
-            "u" => Ok(KeyCode(VirtualKeyCode::U)),
-            "v" => Ok(KeyCode(VirtualKeyCode::V)),
-            "w" => Ok(KeyCode(VirtualKeyCode::W)),
-            "x" => Ok(KeyCode(VirtualKeyCode::X)),
-            "y" => Ok(KeyCode(VirtualKeyCode::Y)),
-            "z" => Ok(KeyCode(VirtualKeyCode::Z)),
-            "1" => Ok(KeyCode(VirtualKeyCode::Key1)),
-            "2" => Ok(KeyCode(VirtualKeyCode::Key2)),
-            "3" => Ok(KeyCode(VirtualKeyCode::Key3)),
-            "4" => Ok(KeyCode(VirtualKeyCode::Key4)),
-            "5" => Ok(KeyCode(VirtualKeyCode::Key5)),
-            "6" => Ok(KeyCode(VirtualKeyCode::Key6)),
-            "7" => Ok(KeyCode(VirtualKeyCode::Key7)),
-            "8" => Ok(KeyCode(VirtualKeyCode::Key8)),
-            "9" => Ok(KeyCode(VirtualKeyCode::Key9)),
-            "0" => Ok(KeyCode(VirtualKeyCode::Key0)),
-            "SPC" => Ok(KeyCode(VirtualKeyCode::Space)),
-            _ => Err(ShortcutFromStrError),
-        }
-    }
-}
-
-impl From<VirtualKeyCode> for KeyCode {
-    fn from(value: VirtualKeyCode) -> Self {
-        KeyCode(value)
+        if let Some((_, key_code)) = KEY_NAMES.iter().find(|(name, _)| *name == value) {
+            return Ok(*key_code);
+        }
+        if RESERVED_KEYS.contains(&value) {
+            return Err(ShortcutFromStrError::ReservedKey(value.to_owned()));
+        }
+        let number = |prefix: &str, range: RangeInclusive<u8>| {
+            value
+                .strip_prefix(prefix)
+                .and_then(|number| number.parse::<u8>().ok())
+                .filter(|number| range.contains(number))
+        };
+        if let Some(number) = number("F", 1..=24) {
+            return Ok(KeyCode::F(number));
+        }
+        if let Some(digit) = number("KP", 0..=9) {
+            return Ok(KeyCode::Numpad(digit));
+        }
+
+        let mut characters = value.chars();
+        match (characters.next(), characters.next()) {
+            (Some(character), None) if !character.is_whitespace() && !character.is_control() => {
+                Ok(KeyCode::Char(character))
+            }
+            _ => Err(ShortcutFromStrError::UnknownKey(value.to_owned())),
+        }
+    }
+}
+
+impl KeyCode {
+    /// The key typing `character`, regardless of its case.
+    pub fn from_char(character: char) -> Option<KeyCode> {
+        match character {
+            ' ' => Some(KeyCode::Space),
+            '\n' | '\r' => Some(KeyCode::Return),
+            '\t' => Some(KeyCode::Tab),
+            _ => character
+                .to_lowercase()
+                .to_string()
+                .parse()
+                .ok()
+                .filter(KeyCode::is_key),
+        }
+    }
+
+    /**
+     * Whether a key of the keyboard is named like this.
+     *
+     * Characters only typed with Shift, or missing on a US layout, are
+     * matched by character.
+     */
+    pub fn is_key(&self) -> bool {
+        match self {
+            KeyCode::Char(character) => {
+                character.is_ascii_lowercase()
+                    || character.is_ascii_digit()
+                    || PUNCTUATION.contains(*character)
+            }
+            _ => true,
+        }
     }
 }
 
 impl Display for KeyCode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let keyname = match self.0 {
-            VirtualKeyCode::A => Some("a"),
-            VirtualKeyCode::B => Some("b"),
-            VirtualKeyCode::C => Some("c"),
-            VirtualKeyCode::D => Some("d"),
-            VirtualKeyCode::E => Some("e"),
-            VirtualKeyCode::F => Some("f"),
-            VirtualKeyCode::G => Some("g"),
-            VirtualKeyCode::H => Some("h"),
-            VirtualKeyCode::I => Some("i"),
-            VirtualKeyCode::J => Some("j"),
-            VirtualKeyCode::K => Some("k"),
-            VirtualKeyCode::L => Some("l"),
-            VirtualKeyCode::M => Some("m"),
-            VirtualKeyCode::N => Some("n"),
-            VirtualKeyCode::O => Some("o"),
-            VirtualKeyCode::P => Some("p"),
-            VirtualKeyCode::Q => Some("q"),
-            VirtualKeyCode::R => Some("r"),
-            VirtualKeyCode::S => Some("s"),
-            VirtualKeyCode::T => Some("t"),
-            VirtualKeyCode::U => Some("u"),
-            VirtualKeyCode::V => Some("v"),
-            VirtualKeyCode::W => Some("w"),
-            VirtualKeyCode::X => Some("x"),
-            VirtualKeyCode::Y => Some("y"),
-            VirtualKeyCode::Z => Some("z"),
-            VirtualKeyCode::Key1 => Some("1"),
-            VirtualKeyCode::Key2 => Some("2"),
-            VirtualKeyCode::Key3 => Some("3"),
-            VirtualKeyCode::Key4 => Some("4"),
-            VirtualKeyCode::Key5 => Some("5"),
-            VirtualKeyCode::Key6 => Some("6"),
-            VirtualKeyCode::Key7 => Some("7"),
-            VirtualKeyCode::Key8 => Some("8"),
-            VirtualKeyCode::Key9 => Some("9"),
-            VirtualKeyCode::Key0 => Some("0"),
-            _ => None,
-        };
-
-        match keyname {
-            Some(name) => write!(f, "{}", name),
-            None => write!(f, "<unknown>"),
+        match self {
+            KeyCode::Char(character) => write!(f, "{}", character),
+            KeyCode::F(number) => write!(f, "F{}", number),
+            KeyCode::Numpad(digit) => write!(f, "KP{}", digit),
+            key_code => {
+                let (name, _) = KEY_NAMES
+                    .iter()
+                    .find(|(_, named)| named == key_code)
+                    .expect("every key without a character has a name");
+                write!(f, "{}", name)
+            }
         }
     }
 }
 
+/**
+ * The modifier keys held while pressing a key
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub logo: bool,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Shortcut {
     pub key_code: KeyCode,
-    pub modifiers: ModifiersState,
+    pub modifiers: Modifiers,
+}
+
+impl From<KeyCode> for Shortcut {
+    fn from(key_code: KeyCode) -> Self {
+        Shortcut {
+            key_code,
+            modifiers: Modifiers::default(),
+        }
+    }
 }
 
 impl Display for Shortcut {
@@ -126,13 +207,43 @@ impl Display for Shortcut {
         if self.modifiers.alt {
             write!(f, "M-")?;
         }
-        if self.modifiers.shift {
+        let uppercase = match self.key_code {
+            KeyCode::Char(character) if self.modifiers.shift && character.is_ascii_lowercase() => {
+                Some(character.to_ascii_uppercase())
+            }
+            _ => None,
+        };
+        if self.modifiers.shift && uppercase.is_none() {
             write!(f, "S-")?;
         }
         if self.modifiers.logo {
             write!(f, "L-")?;
         }
-        write!(f, "{}", self.key_code)
+        match uppercase {
+            Some(character) => write!(f, "{}", character),
+            None => write!(f, "{}", self.key_code),
+        }
+    }
+}
+
+impl Shortcut {
+    /// An uppercase letter is typed with Shift, so `A` is the same as `S-a`.
+    fn with_implicit_shift(mut self) -> Shortcut {
+        if let KeyCode::Char(character) = self.key_code {
+            if character.is_ascii_uppercase() {
+                self.key_code = KeyCode::Char(character.to_ascii_lowercase());
+                self.modifiers.shift = true;
+            }
+        }
+        self
+    }
+
+    /// Whether pressing the key types a character, which Ctrl combinations do not.
+    pub fn types_character(&self) -> bool {
+        match self.key_code {
+            KeyCode::Char(_) | KeyCode::Space => !self.modifiers.ctrl,
+            _ => false,
+        }
     }
 }
 
@@ -140,22 +251,175 @@ impl FromStr for Shortcut {
     type Err = ShortcutFromStrError;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
-        let keys: Vec<&str> = value.split('-').collect::<Vec<&str>>();;
-        if let Some((key_code_string, modifier_strings)) = keys.split_last() {
-            let modifiers = ModifiersState {
-                ctrl: modifier_strings.contains(&"C"),
-                alt: modifier_strings.contains(&"M"),
-                shift: modifier_strings.contains(&"S"),
-                logo: modifier_strings.contains(&"L"),
-            };
-            let key_code = key_code_string.parse()?;
-            Ok(Shortcut {
-                modifiers,
-                key_code
-            })
+        // `-` separates the modifiers, but is a key of its own at the end, e.g. `C--`
+        let (modifier_string, key_code_string) = match value.strip_suffix("--") {
+            _ if value == "-" => ("", value),
+            Some(modifier_string) => (modifier_string, "-"),
+            None => match value.rfind('-') {
+                Some(index) => (&value[..index], &value[index + 1..]),
+                None => ("", value),
+            },
+        };
+        if key_code_string.is_empty() {
+            return Err(ShortcutFromStrError::Empty);
+        }
+        let modifier_strings: Vec<&str> = if modifier_string.is_empty() {
+            vec![]
         } else {
-            Err(ShortcutFromStrError)
+            modifier_string.split('-').collect()
+        };
+        if let Some(unknown) = modifier_strings
+            .iter()
+            .find(|modifier| !["C", "M", "S", "L"].contains(modifier))
+        {
+            return Err(ShortcutFromStrError::UnknownModifier((*unknown).to_owned()));
         }
+        let modifiers = Modifiers {
+            ctrl: modifier_strings.contains(&"C"),
+            alt: modifier_strings.contains(&"M"),
+            shift: modifier_strings.contains(&"S"),
+            logo: modifier_strings.contains(&"L"),
+        };
+        let key_code = key_code_string.parse()?;
+        Ok(Shortcut {
+            modifiers,
+            key_code
+        }
+        .with_implicit_shift())
+    }
+}
+
+/**
+ * How a shortcut is matched against the keys pressed
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Matching {
+    /// By the key pressed, as on a US layout
+    #[default]
+    Key,
+    /// By the character typed, following the keyboard layout
+    Character,
+}
+
+/**
+ * The key running the `cmd` of the menu spacerun is in
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuCmdKey {
+    Shortcut(Shortcut),
+    /// Pressing the menu's own shortcut again, written `repeat`
+    Repeat,
+}
+
+impl Default for MenuCmdKey {
+    fn default() -> Self {
+        MenuCmdKey::Shortcut(KeyCode::Return.into())
+    }
+}
+
+impl<'de> de::Deserialize<'de> for MenuCmdKey {
+    fn deserialize<D>(deserializer: D) -> Result<MenuCmdKey, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.as_str() {
+            "repeat" => Ok(MenuCmdKey::Repeat),
+            _ => FromStr::from_str(&s)
+                .map(MenuCmdKey::Shortcut)
+                .map_err(de::Error::custom),
+        }
+    }
+}
+
+/**
+ * A key pressed in a frontend, as a shortcut of each `Matching`
+ */
+#[derive(Debug, Clone, Default)]
+pub struct KeyPress {
+    /// The key and the modifiers held, missing if the frontend only got a character
+    pub key: Option<Shortcut>,
+    /// The typed character, with Shift being part of the character
+    pub character: Option<Shortcut>,
+}
+
+impl KeyPress {
+    /// The press of `key` that typed `character`.
+    pub fn typed(key: Option<Shortcut>, character: char) -> KeyPress {
+        let mut modifiers = key.as_ref().map(|key| key.modifiers).unwrap_or_default();
+        let key_code = match character {
+            ' ' => Some(KeyCode::Space),
+            '\r' | '\n' => Some(KeyCode::Return),
+            '\t' => Some(KeyCode::Tab),
+            _ => {
+                // Shift is part of the typed character
+                modifiers.shift = false;
+                character.to_string().parse().ok()
+            }
+        };
+        let character = key_code.map(|key_code| Shortcut {
+            key_code,
+            modifiers,
+        });
+        KeyPress {
+            key,
+            character: character.map(Shortcut::with_implicit_shift),
+        }
+    }
+
+    /// Whether the press matches `shortcut`, matched as `matching` says.
+    pub fn matches(&self, shortcut: &Shortcut, matching: Matching) -> bool {
+        let pressed = match (matching, shortcut.key_code) {
+            (Matching::Character, KeyCode::Char(_)) => &self.character,
+            // Symbols like `?` are typed with different keys on each layout
+            (Matching::Key, key_code) if !key_code.is_key() => &self.character,
+            // Keys without a character are the same on every layout
+            _ => &self.key,
+        };
+        pressed.as_ref() == Some(shortcut)
+    }
+}
+
+impl From<Shortcut> for KeyPress {
+    fn from(key: Shortcut) -> Self {
+        // Ctrl combinations type no character, the letter of the key is the one
+        let character = match key.key_code {
+            KeyCode::Char(_) if key.modifiers.ctrl => Some(key.clone()),
+            _ => None,
+        };
+        KeyPress {
+            key: Some(key),
+            character,
+        }
+    }
+}
+
+/**
+ * Whitespace separated shortcuts pressed one after another, e.g. `w n`
+ *
+ * Leads down the command tree, or is the key sequence bound to a single
+ * command, e.g. `C-x C-f`.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShortcutPath(pub Vec<Shortcut>);
+
+impl Display for ShortcutPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let shortcuts: Vec<String> = self.0.iter().map(|shortcut| shortcut.to_string()).collect();
+        write!(f, "{}", shortcuts.join(" "))
+    }
+}
+
+impl FromStr for ShortcutPath {
+    type Err = ShortcutFromStrError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        value
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<Shortcut>, _>>()
+            .map(ShortcutPath)
     }
 }
 
@@ -164,22 +428,57 @@ impl<'de> de::Deserialize<'de> for Shortcut {
     where
         D: de::Deserializer<'de>,
     {
-        let s = <&str>::deserialize(deserializer)?;
-        FromStr::from_str(s).map_err(de::Error::custom)
+        let s = String::deserialize(deserializer)?;
+        FromStr::from_str(&s).map_err(de::Error::custom)
     }
 }
 
-#[derive(Copy, Clone)]
-pub struct ShortcutFromStrError;
+/// Key sequences bound to commands, which need at least one key.
+impl<'de> de::Deserialize<'de> for ShortcutPath {
+    fn deserialize<D>(deserializer: D) -> Result<ShortcutPath, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match ShortcutPath::from_str(&s).map_err(de::Error::custom)? {
+            ShortcutPath(shortcuts) if shortcuts.is_empty() => {
+                Err(de::Error::custom(ShortcutFromStrError::Empty))
+            }
+            path => Ok(path),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum ShortcutFromStrError {
+    Empty,
+    UnknownKey(String),
+    UnknownModifier(String),
+    /// Esc and Backspace, which close spacerun and go back
+    ReservedKey(String),
+}
 
 impl Display for ShortcutFromStrError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Not a valid shortcut identifier")
+        match self {
+            ShortcutFromStrError::Empty => write!(f, "Not a valid shortcut identifier: no key given"),
+            ShortcutFromStrError::UnknownKey(key) => write!(f, "Unknown key `{}`", key),
+            ShortcutFromStrError::UnknownModifier(modifier) => write!(
+                f,
+                "Unknown modifier `{}`, use one of `C`, `M`, `S` or `L`",
+                modifier
+            ),
+            ShortcutFromStrError::ReservedKey(key) => write!(
+                f,
+                "`{}` cannot be bound, spacerun uses it to close the menu or go back",
+                key
+            ),
+        }
     }
 }
 
 impl fmt::Debug for ShortcutFromStrError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Not a valid shortcut identifier")
+        Display::fmt(self, f)
     }
 }
@@ -1,35 +1,154 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::io;
+use std::process::Child;
+
+use serde::de::{self, Deserialize, Deserializer};
 use serde_derive::Deserialize;
 
-use crate::bindings::Shortcut;
+use crate::action::{self, Action, RunSettings, Streams};
+use crate::bindings::{Matching, Shortcut, ShortcutPath};
+use crate::output::Output;
+use crate::reopen::Then;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct CommandNode {
-    pub shortcut: Shortcut,
+    /// The keys to press one after another, usually a single one
+    pub shortcut: ShortcutPath,
     pub name: String,
-    pub cmd: Option<String>,
+    /// What the menu does besides showing its children
+    pub action: Option<Action>,
+    /// Settings of the menu, passed down to its children
+    pub settings: RunSettings,
+    /// What happens to the output of the menu's own action
+    pub output: Option<Output>,
+    /// What spacerun does once the menu's own action finished
+    pub then: Option<Then>,
+    /// Whether the window stays open after running a command of the menu,
+    /// as its parent says if missing
+    pub sticky: Option<bool>,
     pub children: Vec<Command>,
+    /// File whose commands are appended to `children` when loading the config
+    pub include: Option<String>,
+    /// How `shortcut` is matched, the config's `shortcut_matching` if missing
+    pub shortcut_matching: Option<Matching>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct CommandLeaf {
-    pub shortcut: Shortcut,
+    /// The keys to press one after another, usually a single one
+    pub shortcut: ShortcutPath,
     pub name: String,
-    pub cmd: String,
+    pub action: Action,
+    pub settings: RunSettings,
+    /// What happens to the output of the action, detached if missing
+    pub output: Option<Output>,
+    /// What spacerun does once the action finished, nothing if missing
+    pub then: Option<Then>,
+    /// Whether the window stays open after running the command, as the menu
+    /// says if missing
+    pub sticky: Option<bool>,
+    /// How `shortcut` is matched, the config's `shortcut_matching` if missing
+    pub shortcut_matching: Option<Matching>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-#[serde(untagged)]
+#[derive(Debug, Clone)]
 pub enum Command {
     Node(CommandNode),
     Leaf(CommandLeaf),
 }
 
+/**
+ * The fields of both kinds of commands
+ *
+ * Deserializing this first and deciding on the kind of command afterwards
+ * keeps the errors of the fields, which an untagged enum would swallow.
+ */
+#[derive(Deserialize)]
+struct RawCommand {
+    shortcut: ShortcutPath,
+    name: String,
+    /// Shorthand for a `shell` action
+    cmd: Option<String>,
+    action: Option<Action>,
+    cwd: Option<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    shell: Option<String>,
+    output: Option<Output>,
+    then: Option<Then>,
+    sticky: Option<bool>,
+    children: Option<Vec<Command>>,
+    include: Option<String>,
+    shortcut_matching: Option<Matching>,
+}
+
+impl<'de> Deserialize<'de> for Command {
+    fn deserialize<D>(deserializer: D) -> Result<Command, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = RawCommand::deserialize(deserializer)?;
+        let action = match (raw.cmd, raw.action) {
+            (Some(_), Some(_)) => {
+                return Err(de::Error::custom(format!(
+                    "`{}` has both a `cmd` and an `action`, only one of them can run",
+                    raw.name
+                )))
+            }
+            (cmd, action) => action.or_else(|| cmd.map(Action::shell)),
+        };
+        if raw.output == Some(Output::Show) && raw.then.is_some() {
+            return Err(de::Error::custom(format!(
+                "`{}` shows its output in the window, it cannot have a `then`",
+                raw.name
+            )));
+        }
+        if matches!(&raw.shell, Some(shell) if shell.trim().is_empty()) {
+            return Err(de::Error::custom(action::EMPTY_SHELL_ERROR));
+        }
+        let settings = RunSettings {
+            cwd: raw.cwd.as_deref().map(action::expand_home),
+            env: raw.env,
+            shell: raw.shell,
+        };
+        match (raw.children, raw.include, action) {
+            (None, None, Some(action)) => Ok(Command::Leaf(CommandLeaf {
+                shortcut: raw.shortcut,
+                name: raw.name,
+                action,
+                settings,
+                output: raw.output,
+                then: raw.then,
+                sticky: raw.sticky,
+                shortcut_matching: raw.shortcut_matching,
+            })),
+            (None, None, None) => Err(de::Error::custom(format!(
+                "`{}` needs a `cmd` or `action` to run or `children` to show",
+                raw.name
+            ))),
+            (children, include, action) => Ok(Command::Node(CommandNode {
+                shortcut: raw.shortcut,
+                name: raw.name,
+                action,
+                settings,
+                output: raw.output,
+                then: raw.then,
+                sticky: raw.sticky,
+                children: children.unwrap_or_default(),
+                include,
+                shortcut_matching: raw.shortcut_matching,
+            })),
+        }
+    }
+}
+
 /**
  * Easily displayable command
  */
 #[derive(Clone)]
 pub struct CommandDisplay {
-    pub shortcut: Shortcut,
+    pub shortcut: ShortcutPath,
     pub name: String,
 }
 
@@ -60,14 +179,95 @@ impl From<CommandLeaf> for CommandDisplay {
     }
 }
 
+impl CommandLeaf {
+    /// Start the leaf's action without waiting for it.
+    pub fn spawn(&self, streams: Streams) -> io::Result<Child> {
+        self.action.spawn(&self.settings, streams)
+    }
+}
+
+impl CommandNode {
+    /// The node's own action as a command to run, if it has one.
+    pub fn cmd_leaf(&self) -> Option<CommandLeaf> {
+        Some(CommandLeaf {
+            shortcut: self.shortcut.clone(),
+            name: self.name.clone(),
+            action: self.action.clone()?,
+            settings: self.settings.clone(),
+            output: self.output,
+            then: self.then.clone(),
+            sticky: self.sticky,
+            shortcut_matching: self.shortcut_matching,
+        })
+    }
+}
+
 impl Command {
-    pub fn shortcut(&self) -> &Shortcut {
+    pub fn shortcut(&self) -> &ShortcutPath {
         match self {
             Command::Leaf(command_leaf) => &command_leaf.shortcut,
             Command::Node(command_node) => &command_node.shortcut,
         }
     }
 
+    /// How the shortcut is matched, by key unless the config says otherwise.
+    pub fn shortcut_matching(&self) -> Matching {
+        let shortcut_matching = match self {
+            Command::Leaf(command_leaf) => command_leaf.shortcut_matching,
+            Command::Node(command_node) => command_node.shortcut_matching,
+        };
+        shortcut_matching.unwrap_or_default()
+    }
+
+    /// Match every shortcut without a matching of its own as `matching`.
+    pub fn set_default_matching(&mut self, matching: Matching) {
+        match self {
+            Command::Leaf(command_leaf) => {
+                command_leaf.shortcut_matching.get_or_insert(matching);
+            }
+            Command::Node(command_node) => {
+                command_node.shortcut_matching.get_or_insert(matching);
+                for child in &mut command_node.children {
+                    child.set_default_matching(matching);
+                }
+            }
+        }
+    }
+
+    /**
+     * Pass the settings of every menu down to its children.
+     *
+     * `inherited` are the settings this command is inside of.
+     */
+    pub fn inherit_settings(&mut self, inherited: &RunSettings) {
+        match self {
+            Command::Leaf(command_leaf) => {
+                command_leaf.settings = command_leaf.settings.inherit(inherited);
+            }
+            Command::Node(command_node) => {
+                command_node.settings = command_node.settings.inherit(inherited);
+                for child in &mut command_node.children {
+                    child.inherit_settings(&command_node.settings);
+                }
+            }
+        }
+    }
+
+    /// Whether the window stays open after running the command, if it says so.
+    pub fn sticky(&self) -> Option<bool> {
+        match self {
+            Command::Leaf(command_leaf) => command_leaf.sticky,
+            Command::Node(command_node) => command_node.sticky,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Command::Leaf(command_leaf) => &command_leaf.name,
+            Command::Node(command_node) => &command_node.name,
+        }
+    }
+
     pub fn displayable_children(&self) -> Vec<CommandDisplay> {
         match self {
             Command::Leaf(command_leaf) => vec![command_leaf.clone().into()],
@@ -82,13 +282,121 @@ impl Command {
         }
     }
 
-    pub fn find_child_for_shortcut(&self, shortcut: &Shortcut) -> Option<&Command> {
-        if let Command::Node(node) = self {
-            node.children
-                .iter()
-                .find(|&child| child.shortcut() == shortcut)
-        } else {
-            None
+    /// The children of a node, none for a leaf.
+    pub fn children(&self) -> &[Command] {
+        match self {
+            Command::Leaf(_) => &[],
+            Command::Node(command_node) => &command_node.children,
         }
     }
+
+    /**
+     * The child whose shortcut `path` starts with.
+     *
+     * If the shortcuts of several children fit, the shortest one wins, as
+     * it is finished first when pressing the keys.
+     */
+    pub fn find_child_for_path(&self, path: &[Shortcut]) -> Option<&Command> {
+        self.children()
+            .iter()
+            .filter(|child| path.starts_with(&child.shortcut().0))
+            .min_by_key(|child| child.shortcut().0.len())
+    }
+
+    /**
+     * Put the command tree `upper` on top of this one.
+     *
+     * Children of two nodes bound to the same shortcut are merged
+     * recursively, in every other case the upper command replaces the lower
+     * one. Children only `upper` has are appended to the lower ones.
+     */
+    pub fn merge(self, upper: Command) -> Command {
+        match (self, upper) {
+            (Command::Node(lower), Command::Node(upper)) => {
+                let mut children = lower.children;
+                for upper_child in upper.children {
+                    let position = children
+                        .iter()
+                        .position(|child| child.shortcut() == upper_child.shortcut());
+                    match position {
+                        Some(position) => {
+                            let lower_child = children.remove(position);
+                            children.insert(position, lower_child.merge(upper_child));
+                        }
+                        None => children.push(upper_child),
+                    }
+                }
+                Command::Node(CommandNode {
+                    shortcut: upper.shortcut,
+                    name: upper.name,
+                    action: upper.action.or(lower.action),
+                    settings: lower.settings.merge(upper.settings),
+                    output: upper.output.or(lower.output),
+                    then: upper.then.or(lower.then),
+                    sticky: upper.sticky.or(lower.sticky),
+                    children,
+                    include: upper.include.or(lower.include),
+                    shortcut_matching: upper.shortcut_matching.or(lower.shortcut_matching),
+                })
+            }
+            (_, upper) => upper,
+        }
+    }
+
+    /**
+     * Walk down the tree along `path`, starting at this command.
+     *
+     * Returns every command passed on the way, the last one being the
+     * command the path points to. A leading shortcut naming this command
+     * itself is skipped, so for a root bound to `r` both `r w n` and `w n`
+     * resolve to the same command.
+     */
+    pub fn resolve_path(&self, path: &[Shortcut]) -> Result<Vec<&Command>, UnknownShortcutError> {
+        let own_shortcut = &self.shortcut().0;
+        let mut path = match path {
+            _ if path.starts_with(own_shortcut) && self.find_child_for_path(path).is_none() => {
+                &path[own_shortcut.len()..]
+            }
+            _ => path,
+        };
+
+        let mut commands: Vec<&Command> = vec![];
+        while let Some(shortcut) = path.first() {
+            let parent = commands.last().cloned().unwrap_or(self);
+            match parent.find_child_for_path(path) {
+                Some(child) => {
+                    path = &path[child.shortcut().0.len()..];
+                    commands.push(child);
+                }
+                None => {
+                    return Err(UnknownShortcutError {
+                        shortcut: shortcut.clone(),
+                        breadcrumbs: commands.iter().fold("Root".into(), |acc, command| {
+                            format!("{} > {}", acc, command.name())
+                        }),
+                    })
+                }
+            }
+        }
+        Ok(commands)
+    }
+}
+
+/**
+ * A shortcut path walked into a command that has no child for one of its keys
+ */
+#[derive(Debug, Clone)]
+pub struct UnknownShortcutError {
+    pub shortcut: Shortcut,
+    pub breadcrumbs: String,
+}
+
+impl Display for UnknownShortcutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "No command is bound to `{}` in `{}`",
+            self.shortcut, self.breadcrumbs
+        )
+    }
 }
@@ -1,31 +1,614 @@
+use std::env;
 use std::error::Error;
-use std::fs::File;
-use std::io::prelude::*;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
 
 use directories::ProjectDirs;
+use serde::de::DeserializeOwned;
 use serde_derive::Deserialize;
+use serde_json::Value;
 
-use crate::commands::Command;
+use crate::action::RunSettings;
+use crate::bindings::{Matching, MenuCmdKey, Shortcut};
+use crate::commands::{Command, CommandNode};
+use crate::config_format::{ConfigFormat, Location, ParseError, CONFIG_EXTENSIONS};
+use crate::locations::{self, field_path};
 use crate::window_position::WindowPosition;
+use crate::Options;
+
+static CONFIG_FILE_STEM: &str = "config";
+static DEFAULT_XDG_CONFIG_DIRS: &str = "/etc/xdg";
+static STARTER_CONFIG: &str = include_str!("../assets/starter-config.yaml");
+static INCLUDED_LEAF_ERROR: &str = "an included node needs `children`";
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct SpacerunConfig {
     pub commands: Command,
     pub font_size: Option<u32>,
     pub position: Option<WindowPosition>,
+    /// How shortcuts are matched unless a command says otherwise
+    pub shortcut_matching: Option<Matching>,
+    /// The key running the `cmd` of the current menu, `RET` if missing
+    pub menu_cmd_key: Option<MenuCmdKey>,
+    /// The key toggling whether the window stays open after running a
+    /// command, `C-s` if missing
+    pub sticky_key: Option<Shortcut>,
+    /// The files the config was loaded from, including the included ones
+    #[serde(skip)]
+    pub sources: Vec<PathBuf>,
 }
 
-pub fn load_config() -> Result<SpacerunConfig, Box<Error>> {
-    let mut config_dir = ProjectDirs::from("cc", "linu", "spacerun")
-        .unwrap()
-        .config_dir()
-        .to_owned();
-    config_dir.push("config.json");
-    println!("CFG dir {:?}", config_dir);
-    let mut file = File::open(config_dir)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    println!("contents: {}", contents);
-    let config: SpacerunConfig = serde_json::from_str(&contents)?;
+impl SpacerunConfig {
+    /// A config without any commands.
+    pub fn empty() -> SpacerunConfig {
+        SpacerunConfig {
+            commands: Command::Node(CommandNode {
+                shortcut: "SPC".parse().expect("SPC is a valid shortcut"),
+                name: "Root".into(),
+                action: None,
+                settings: RunSettings::default(),
+                output: None,
+                then: None,
+                sticky: None,
+                children: vec![],
+                include: None,
+                shortcut_matching: None,
+            }),
+            font_size: None,
+            position: None,
+            shortcut_matching: None,
+            menu_cmd_key: None,
+            sticky_key: None,
+            sources: vec![],
+        }
+    }
+
+    /**
+     * Put the config layer `upper` on top of this one.
+     *
+     * Options set in `upper` win, command trees are merged (see
+     * `Command::merge`).
+     */
+    pub fn merge(self, upper: SpacerunConfig) -> SpacerunConfig {
+        SpacerunConfig {
+            commands: self.commands.merge(upper.commands),
+            font_size: upper.font_size.or(self.font_size),
+            position: upper.position.or(self.position),
+            shortcut_matching: upper.shortcut_matching.or(self.shortcut_matching),
+            menu_cmd_key: upper.menu_cmd_key.or(self.menu_cmd_key),
+            sticky_key: upper.sticky_key.or(self.sticky_key),
+            sources: self.sources.into_iter().chain(upper.sources).collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// No config file in any of the searched directories
+    NotFound(Vec<PathBuf>),
+    Io(PathBuf, io::Error),
+    UnknownFormat(PathBuf),
+    Parse(PathBuf, ParseError),
+    /// A well-formed file whose content is no valid config
+    Invalid(PathBuf, InvalidConfig),
+    /// Paths of the fields spacerun does not know, only an error with `--strict`
+    UnknownFields(PathBuf, Vec<String>),
+    /// A file included as children that contains no commands
+    InvalidInclude(PathBuf, String),
+    /// The files of an include chain, ending with the one included twice
+    IncludeCycle(Vec<PathBuf>),
+    /// An error in a file included by the last file of the chain
+    Include(Vec<PathBuf>, Box<ConfigError>),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::NotFound(searched_dirs) => {
+                write!(
+                    f,
+                    "No config file ({}.{{{}}}) found. Searched:",
+                    CONFIG_FILE_STEM,
+                    CONFIG_EXTENSIONS.join(",")
+                )?;
+                for dir in searched_dirs {
+                    write!(f, "\n  {}", dir.display())?;
+                }
+                Ok(())
+            }
+            ConfigError::Io(path, err) => write!(f, "Could not read {}: {}", path.display(), err),
+            ConfigError::UnknownFormat(path) => write!(
+                f,
+                "Unknown config format of {}, supported extensions are: {}",
+                path.display(),
+                CONFIG_EXTENSIONS.join(", ")
+            ),
+            ConfigError::Parse(path, err) => write!(f, "Error in {}: {}", path.display(), err),
+            ConfigError::Invalid(path, err) => write!(f, "Error in {}{}", path.display(), err),
+            ConfigError::UnknownFields(path, fields) => {
+                let fields: Vec<String> = fields.iter().map(|field| format!("`{}`", field)).collect();
+                write!(f, "Unknown fields in {}: {}", path.display(), fields.join(", "))
+            }
+            ConfigError::InvalidInclude(path, reason) => {
+                write!(f, "Invalid include in {}: {}", path.display(), reason)
+            }
+            ConfigError::IncludeCycle(chain) => {
+                write!(f, "Include cycle: {}", display_include_chain(chain))
+            }
+            ConfigError::Include(chain, err) => {
+                write!(f, "{}\n  included from {}", err, display_include_chain(chain))
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/**
+ * The reason a value of a config file is invalid
+ */
+#[derive(Debug)]
+pub struct InvalidConfig {
+    /// Path of the value, e.g. `commands.children[3].shortcut`
+    pub path: String,
+    pub message: String,
+}
+
+impl Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, ": {}", self.message)
+        } else {
+            write!(f, " at `{}`: {}", self.path, self.message)
+        }
+    }
+}
+
+impl ConfigError {
+    /// The file the error is about, if it is about a single one.
+    pub fn file(&self) -> Option<&Path> {
+        match self {
+            ConfigError::NotFound(_) => None,
+            ConfigError::Io(path, _)
+            | ConfigError::UnknownFormat(path)
+            | ConfigError::Parse(path, _)
+            | ConfigError::Invalid(path, _)
+            | ConfigError::UnknownFields(path, _)
+            | ConfigError::InvalidInclude(path, _) => Some(path),
+            ConfigError::IncludeCycle(chain) => chain.iter().rev().nth(1).map(PathBuf::as_path),
+            // A missing included file is an error of the file including it
+            ConfigError::Include(chain, err) => match &**err {
+                ConfigError::Io(_, io_err) if io_err.kind() == io::ErrorKind::NotFound => {
+                    chain.last().map(PathBuf::as_path)
+                }
+                err => err.file(),
+            },
+        }
+    }
+
+    /**
+     * Where in `file` the error is.
+     *
+     * Errors of a value are located by reading the file again, which only
+     * works for some formats (see `locations::locate`).
+     */
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            ConfigError::Parse(_, err) => err.location,
+            ConfigError::Invalid(path, err) => locations::locate_in_file(path, &err.path),
+            ConfigError::UnknownFields(path, fields) => fields
+                .first()
+                .and_then(|field| locations::locate_in_file(path, field)),
+            ConfigError::Include(_, err) => err.location(),
+            _ => None,
+        }
+    }
+}
+
+fn display_include_chain(chain: &[PathBuf]) -> String {
+    let files: Vec<String> = chain.iter().map(|path| path.display().to_string()).collect();
+    files.join(" -> ")
+}
+
+/**
+ * Load the config.
+ *
+ * Every file of `config_files` is loaded, later files being merged on top of
+ * earlier ones.
+ */
+pub fn load_config(options: &Options) -> Result<SpacerunConfig, ConfigError> {
+    let mut config: Option<SpacerunConfig> = None;
+    for path in config_files(options)? {
+        let layer = load_config_file(&path, options.strict)?;
+        config = Some(match config {
+            Some(lower) => lower.merge(layer),
+            None => layer,
+        });
+    }
+    let mut config = config.expect("config_files returns at least one file");
+    let matching = config.shortcut_matching.unwrap_or_default();
+    config.commands.set_default_matching(matching);
+    config.commands.inherit_settings(&RunSettings::default());
+    Ok(config)
+}
+
+/**
+ * The config files to load, from the lowest to the highest priority.
+ *
+ * A config file given by `--config` or `$SPACERUN_CONFIG` is used on its
+ * own. Otherwise these are the config files found in the XDG config
+ * directories, see `layered_config_dirs`.
+ */
+pub fn config_files(options: &Options) -> Result<Vec<PathBuf>, ConfigError> {
+    if let Some(path) = explicit_config_path(options) {
+        return Ok(vec![path]);
+    }
+
+    let dirs = layered_config_dirs();
+    let files: Vec<PathBuf> = dirs.iter().filter_map(|dir| find_config_file(dir)).collect();
+    if files.is_empty() {
+        Err(ConfigError::NotFound(dirs))
+    } else {
+        Ok(files)
+    }
+}
+
+fn explicit_config_path(options: &Options) -> Option<PathBuf> {
+    options
+        .config
+        .clone()
+        .or_else(|| env::var_os("SPACERUN_CONFIG").map(PathBuf::from))
+}
+
+/**
+ * Every path a config file of `config_files` could have, whether it exists
+ * or not.
+ */
+pub fn config_file_candidates(options: &Options) -> Vec<PathBuf> {
+    if let Some(path) = explicit_config_path(options) {
+        return vec![path];
+    }
+
+    layered_config_dirs()
+        .iter()
+        .flat_map(|dir| {
+            CONFIG_EXTENSIONS
+                .iter()
+                .map(move |extension| dir.join(CONFIG_FILE_STEM).with_extension(extension))
+        })
+        .collect()
+}
+
+/**
+ * Directories whose config files are merged, from the lowest to the highest
+ * priority.
+ *
+ * The system-wide directories of `$XDG_CONFIG_DIRS` come first (its first
+ * entry being the most important one), followed by the user's config
+ * directory.
+ */
+fn layered_config_dirs() -> Vec<PathBuf> {
+    let xdg_config_dirs = env::var_os("XDG_CONFIG_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| DEFAULT_XDG_CONFIG_DIRS.into());
+    let mut dirs: Vec<PathBuf> = env::split_paths(&xdg_config_dirs)
+        .map(|dir| dir.join("spacerun"))
+        .collect();
+    dirs.reverse();
+
+    if let Some(project_dirs) = ProjectDirs::from("cc", "linu", "spacerun") {
+        dirs.push(project_dirs.config_dir().to_owned());
+    }
+    dirs
+}
+
+/**
+ * Write the commented starter config into the user's config directory.
+ *
+ * Used on the first run, when no config file exists yet. Returns the path of
+ * the written file.
+ */
+pub fn write_starter_config() -> Result<PathBuf, ConfigError> {
+    let dir = ProjectDirs::from("cc", "linu", "spacerun")
+        .map(|project_dirs| project_dirs.config_dir().to_owned())
+        .ok_or_else(|| ConfigError::NotFound(layered_config_dirs()))?;
+    let path = dir.join(CONFIG_FILE_STEM).with_extension("yaml");
+    eprintln!("Writing starter config to {}", path.display());
+    fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&path, STARTER_CONFIG))
+        .map_err(|err| ConfigError::Io(path.clone(), err))?;
+    Ok(path)
+}
+
+/// The config file in `dir`, trying each supported extension in turn.
+fn find_config_file(dir: &Path) -> Option<PathBuf> {
+    CONFIG_EXTENSIONS
+        .iter()
+        .map(|extension| dir.join(CONFIG_FILE_STEM).with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+fn load_config_file(path: &Path, strict: bool) -> Result<SpacerunConfig, ConfigError> {
+    let value = read_config_value(path)?;
+    let mut config: SpacerunConfig = deserialize_value(path, value, strict)?;
+    config.sources.push(canonical_path(path));
+    let mut chain = vec![canonical_path(path)];
+    resolve_includes(&mut config.commands, &mut chain, &mut config.sources, strict)?;
     Ok(config)
 }
+
+fn read_config_value(path: &Path) -> Result<Value, ConfigError> {
+    let (format, contents) = read_config_file(path)?;
+    format
+        .parse(&contents)
+        .map_err(|err| ConfigError::Parse(path.to_owned(), err))
+}
+
+/// Read a config file and find out its format.
+pub fn read_config_file(path: &Path) -> Result<(ConfigFormat, String), ConfigError> {
+    let format =
+        ConfigFormat::from_path(path).ok_or_else(|| ConfigError::UnknownFormat(path.to_owned()))?;
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|err| ConfigError::Io(path.to_owned(), err))?;
+    Ok((format, contents))
+}
+
+/**
+ * Deserialize the `value` read from the config file `path`.
+ *
+ * Errors name the path of the offending value, e.g.
+ * `commands.children[3].shortcut`. With `strict`, fields spacerun does not
+ * know are errors as well.
+ */
+fn deserialize_value<T>(path: &Path, value: Value, strict: bool) -> Result<T, ConfigError>
+where
+    T: DeserializeOwned,
+{
+    let mut unknown_fields = vec![];
+    let deserialized = {
+        let mut on_unknown_field =
+            |field: serde_ignored::Path| unknown_fields.push(ignored_field_path(&field));
+        serde_path_to_error::deserialize(serde_ignored::Deserializer::new(
+            value,
+            &mut on_unknown_field,
+        ))
+    };
+
+    let deserialized = deserialized.map_err(|err| {
+        let value_path = match err.path().to_string().as_str() {
+            "." => String::new(),
+            value_path => value_path.to_owned(),
+        };
+        ConfigError::Invalid(
+            path.to_owned(),
+            InvalidConfig {
+                path: value_path,
+                message: err.into_inner().to_string(),
+            },
+        )
+    })?;
+    if strict && !unknown_fields.is_empty() {
+        return Err(ConfigError::UnknownFields(path.to_owned(), unknown_fields));
+    }
+    Ok(deserialized)
+}
+
+fn ignored_field_path(path: &serde_ignored::Path) -> String {
+    match path {
+        serde_ignored::Path::Root => String::new(),
+        serde_ignored::Path::Seq { parent, index } => {
+            format!("{}[{}]", ignored_field_path(parent), index)
+        }
+        serde_ignored::Path::Map { parent, key } => field_path(&ignored_field_path(parent), key),
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => ignored_field_path(parent),
+    }
+}
+
+pub fn canonical_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+/// Path of the file `include` of a node in the config file `including_path`.
+pub fn include_path(including_path: &Path, include: &str) -> PathBuf {
+    canonical_path(
+        &including_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(include),
+    )
+}
+
+/**
+ * Append the commands of the files included by nodes to their children, all
+ * the way down the tree of `command`.
+ *
+ * Include paths are relative to the including file, which is the last file
+ * of `chain`.
+ */
+fn resolve_includes(
+    command: &mut Command,
+    chain: &mut Vec<PathBuf>,
+    sources: &mut Vec<PathBuf>,
+    strict: bool,
+) -> Result<(), ConfigError> {
+    if let Command::Node(node) = command {
+        let include = node.include.take();
+        resolve_children_includes(&mut node.children, include, chain, sources, strict)?;
+    }
+    Ok(())
+}
+
+fn resolve_children_includes(
+    children: &mut Vec<Command>,
+    include: Option<String>,
+    chain: &mut Vec<PathBuf>,
+    sources: &mut Vec<PathBuf>,
+    strict: bool,
+) -> Result<(), ConfigError> {
+    for child in children.iter_mut() {
+        resolve_includes(child, chain, sources, strict)?;
+    }
+    if let Some(include) = include {
+        let included = load_include(&include, chain, sources, strict)?;
+        children.extend(included);
+    }
+    Ok(())
+}
+
+/**
+ * How an included file contains its commands
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncludedCommands {
+    /// A list of commands
+    List,
+    /// A node, whose children and include are used
+    Node,
+}
+
+/**
+ * The file `include` of a node in the last file of `chain`.
+ *
+ * Fails if the file does not exist or is part of `chain`, which would include
+ * it over and over again.
+ */
+pub fn find_include(chain: &[PathBuf], include: &str) -> Result<PathBuf, ConfigError> {
+    let including_path = chain.last().cloned().unwrap_or_default();
+    let path = include_path(&including_path, include);
+    if chain.contains(&path) {
+        let mut cycle = chain.to_owned();
+        cycle.push(path);
+        return Err(ConfigError::IncludeCycle(cycle));
+    }
+    if !path.is_file() {
+        let err = io::Error::new(io::ErrorKind::NotFound, "the file does not exist");
+        return Err(ConfigError::Io(path, err));
+    }
+    Ok(path)
+}
+
+/// How the included file `path`, read as `value`, contains its commands.
+pub fn included_commands(path: &Path, value: &Value) -> Result<IncludedCommands, ConfigError> {
+    match value {
+        Value::Array(_) => Ok(IncludedCommands::List),
+        Value::Object(node) if node.contains_key("children") || node.contains_key("include") => {
+            Ok(IncludedCommands::Node)
+        }
+        Value::Object(_) => Err(ConfigError::InvalidInclude(
+            path.to_owned(),
+            INCLUDED_LEAF_ERROR.into(),
+        )),
+        _ => Err(ConfigError::InvalidInclude(
+            path.to_owned(),
+            "an included file must contain a list of commands or a node".into(),
+        )),
+    }
+}
+
+/**
+ * Load the commands of an included file.
+ *
+ * The file either contains a list of commands or a single node, whose
+ * children are used.
+ */
+fn load_include(
+    include: &str,
+    chain: &mut Vec<PathBuf>,
+    sources: &mut Vec<PathBuf>,
+    strict: bool,
+) -> Result<Vec<Command>, ConfigError> {
+    let included_error = |chain: &[PathBuf], err| ConfigError::Include(chain.to_owned(), Box::new(err));
+    let path = find_include(chain, include).map_err(|err| match err {
+        ConfigError::IncludeCycle(_) => err,
+        err => included_error(chain, err),
+    })?;
+    let value = read_config_value(&path).map_err(|err| included_error(chain, err))?;
+    let included = included_commands(&path, &value).map_err(|err| included_error(chain, err))?;
+    let (mut children, include) = match included {
+        IncludedCommands::List => (
+            deserialize_value(&path, value, strict).map_err(|err| included_error(chain, err))?,
+            None,
+        ),
+        IncludedCommands::Node => match deserialize_value(&path, value, strict) {
+            Ok(Command::Node(node)) => (node.children, node.include),
+            Ok(Command::Leaf(_)) => {
+                let err = ConfigError::InvalidInclude(path, INCLUDED_LEAF_ERROR.into());
+                return Err(included_error(chain, err));
+            }
+            Err(err) => return Err(included_error(chain, err)),
+        },
+    };
+
+    sources.push(path.clone());
+    chain.push(path);
+    let resolved = resolve_children_includes(&mut children, include, chain, sources, strict);
+    chain.pop();
+    resolved.map(|_| children)
+}
+
+#[cfg(test)]
+mod tests {
+    use structopt::StructOpt;
+
+    use super::*;
+
+    /// An empty directory for the config files of the test `name`.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("spacerun-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        canonical_path(&dir)
+    }
+
+    #[test]
+    fn missing_include_keeps_the_include_chain() {
+        let dir = test_dir("missing-include");
+        let root = r#"{"commands": {"shortcut": "r", "name": "Root", "include": "a.json"}}"#;
+        fs::write(dir.join("config.json"), root).unwrap();
+        let a = r#"[{"shortcut": "a", "name": "A", "include": "b.json"}]"#;
+        fs::write(dir.join("a.json"), a).unwrap();
+        let b = r#"[{"shortcut": "b", "name": "B", "include": "missing.json"}]"#;
+        fs::write(dir.join("b.json"), b).unwrap();
+
+        let err = load_config_file(&dir.join("config.json"), false).unwrap_err();
+        match &err {
+            ConfigError::Include(chain, included) => {
+                let files = ["config.json", "a.json", "b.json"];
+                let expected: Vec<PathBuf> = files.iter().map(|file| dir.join(file)).collect();
+                assert_eq!(chain, &expected);
+                match &**included {
+                    ConfigError::Io(path, _) => assert_eq!(path, &dir.join("missing.json")),
+                    err => panic!("expected the missing file, got {:?}", err),
+                }
+            }
+            err => panic!("expected an include error, got {:?}", err),
+        }
+        assert_eq!(err.file(), Some(dir.join("b.json").as_path()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn starter_config_is_the_example_config() {
+        let dir = test_dir("starter-config");
+        let path = dir.join("config.yaml");
+        fs::write(&path, STARTER_CONFIG).unwrap();
+        let args = ["spacerun", "--strict", "--config", path.to_str().unwrap()];
+        let options = Options::from_iter(&args);
+        let config = load_config(&options).unwrap();
+        assert_eq!(config.commands.name(), "Root");
+
+        let example = include_str!("../config.json");
+        assert_eq!(
+            ConfigFormat::Yaml.parse(STARTER_CONFIG).unwrap(),
+            ConfigFormat::Json.parse(example).unwrap(),
+            "assets/starter-config.yaml and config.json differ"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
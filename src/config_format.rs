@@ -0,0 +1,126 @@
+use std::fmt::{self, Display};
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::kdl;
+
+/**
+ * File format of a config file, picked by its extension
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+    Kdl,
+}
+
+pub static CONFIG_EXTENSIONS: &[&str] = &["json", "toml", "yaml", "yml", "kdl"];
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Option<ConfigFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "json" => Some(ConfigFormat::Json),
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "kdl" => Some(ConfigFormat::Kdl),
+            _ => None,
+        }
+    }
+
+    /// Parse `contents` into the format independent `Value` tree.
+    pub fn parse(self, contents: &str) -> Result<Value, ParseError> {
+        match self {
+            ConfigFormat::Json => serde_json::from_str(contents).map_err(|err| ParseError {
+                format: self,
+                location: match (err.line(), err.column()) {
+                    (0, _) => None,
+                    (line, column) => Some(Location { line, column }),
+                },
+                message: err.to_string(),
+            }),
+            ConfigFormat::Toml => toml::from_str(contents).map_err(|err| ParseError {
+                format: self,
+                // toml counts from zero
+                location: err.line_col().map(|(line, column)| Location {
+                    line: line + 1,
+                    column: column + 1,
+                }),
+                message: err.to_string(),
+            }),
+            ConfigFormat::Yaml => serde_yaml::from_str(contents).map_err(|err| ParseError {
+                format: self,
+                location: err.location().map(|location| Location {
+                    line: location.line(),
+                    column: location.column(),
+                }),
+                message: err.to_string(),
+            }),
+            ConfigFormat::Kdl => kdl::from_str(contents).map_err(|err| ParseError {
+                format: self,
+                location: Some(Location {
+                    line: err.line,
+                    column: err.column,
+                }),
+                message: err.to_string(),
+            }),
+        }
+    }
+}
+
+impl Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ConfigFormat::Json => "JSON",
+            ConfigFormat::Toml => "TOML",
+            ConfigFormat::Yaml => "YAML",
+            ConfigFormat::Kdl => "KDL",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/**
+ * A position in a config file, counting lines and columns from one
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// The location of the byte `offset` of `input`.
+    pub fn at_offset(input: &str, offset: usize) -> Location {
+        let before = &input[..offset];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        Location {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/**
+ * A config file that is no valid document of its format
+ */
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub format: ConfigFormat,
+    pub location: Option<Location>,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid {}: {}", self.format, self.message)
+    }
+}
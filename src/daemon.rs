@@ -0,0 +1,449 @@
+use std::env;
+use std::fmt::{self, Display};
+use std::fs::{self, DirBuilder};
+use std::io::{self, prelude::*, BufReader};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use conrod::backend::glium::glium::glutin::EventsLoopProxy;
+use conrod::backend::glium::glium::Display as GliumDisplay;
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+
+use crate::bindings::{Shortcut, ShortcutPath};
+use crate::commands::Command;
+use crate::config;
+use crate::exec::{self, RunStatus};
+use crate::navigator;
+use crate::output::Output;
+use crate::state::State;
+use crate::view::update_window_and_window_state;
+
+static SOCKET_FILE_NAME: &str = "spacerun.sock";
+static JSONRPC_VERSION: &str = "2.0";
+
+/**
+ * A method call of the control protocol, see the README for details
+ */
+#[derive(Debug)]
+pub enum Call {
+    /// Show the window with the menu reached by the shortcuts
+    Show(Vec<Shortcut>),
+    Hide,
+    /// Run the command reached by the shortcuts
+    Exec(Vec<Shortcut>),
+    Reload,
+    /// The entries of the menu reached by the shortcuts
+    List(Vec<Shortcut>),
+    Status,
+    /// The last command started and how it ended
+    LastResult,
+}
+
+impl Call {
+    fn from_request(method: &str, params: &Value) -> Result<Call, RpcError> {
+        match method {
+            "show" => Ok(Call::Show(path_param(params)?)),
+            "hide" => Ok(Call::Hide),
+            "exec" => Ok(Call::Exec(path_param(params)?)),
+            "reload" => Ok(Call::Reload),
+            "list" => Ok(Call::List(path_param(params)?)),
+            "status" => Ok(Call::Status),
+            "last_result" => Ok(Call::LastResult),
+            _ => Err(RpcError::new(
+                RpcError::METHOD_NOT_FOUND,
+                format!("Unknown method `{}`", method),
+            )),
+        }
+    }
+}
+
+/// The `path` parameter, given by name or as the only positional parameter.
+fn path_param(params: &Value) -> Result<Vec<Shortcut>, RpcError> {
+    let path = match params {
+        Value::Null => None,
+        Value::Object(params) => params.get("path"),
+        Value::Array(params) => params.first(),
+        _ => {
+            let message = "`params` must be an object or an array";
+            return Err(RpcError::new(RpcError::INVALID_REQUEST, message));
+        }
+    };
+    match path {
+        None | Some(Value::Null) => Ok(vec![]),
+        Some(Value::String(path)) => path
+            .parse::<ShortcutPath>()
+            .map(|path| path.0)
+            .map_err(|err| RpcError::new(RpcError::INVALID_PARAMS, err.to_string())),
+        Some(_) => {
+            let message = "`path` must be a string of shortcuts, e.g. \"w n\"";
+            Err(RpcError::new(RpcError::INVALID_PARAMS, message))
+        }
+    }
+}
+
+/**
+ * A call waiting for the window to handle it, its result is sent to `reply`
+ */
+pub struct Request {
+    pub call: Call,
+    pub reply: Sender<Result<Value, RpcError>>,
+}
+
+/**
+ * A JSON-RPC error object
+ */
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    /// A valid call that could not be carried out, e.g. for an unknown shortcut
+    pub const CALL_FAILED: i64 = -32000;
+
+    pub fn new<S: Into<String>>(code: i64, message: S) -> RpcError {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn call_failed<S: Into<String>>(message: S) -> RpcError {
+        RpcError::new(RpcError::CALL_FAILED, message)
+    }
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawRequest {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    /// Missing for notifications, which get no response
+    id: Option<Value>,
+}
+
+/**
+ * Path of the socket the daemon listens on.
+ *
+ * Without `$XDG_RUNTIME_DIR` it is in a directory of the user inside the
+ * temporary directory, which all users share.
+ */
+pub fn socket_path() -> PathBuf {
+    runtime_dir()
+        .unwrap_or_else(private_socket_dir)
+        .join(SOCKET_FILE_NAME)
+}
+
+fn runtime_dir() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+fn private_socket_dir() -> PathBuf {
+    env::temp_dir().join(format!("spacerun-{}", current_uid()))
+}
+
+fn current_uid() -> u32 {
+    // getuid cannot fail
+    unsafe { libc::getuid() }
+}
+
+/**
+ * Make sure the directory of the socket is one only the user can access.
+ *
+ * Nothing to do for `$XDG_RUNTIME_DIR`, which is private already. With
+ * `create` the directory is created if missing.
+ */
+fn check_socket_dir(create: bool) -> io::Result<()> {
+    if runtime_dir().is_some() {
+        return Ok(());
+    }
+    let dir = private_socket_dir();
+    if create {
+        if let Err(err) = DirBuilder::new().mode(0o700).create(&dir) {
+            if err.kind() != io::ErrorKind::AlreadyExists {
+                return Err(err);
+            }
+        }
+    }
+    let metadata = fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir() || metadata.uid() != current_uid() || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} has to be a directory only you can access",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/**
+ * Listen on the daemon socket for requests.
+ *
+ * Every request received wakes up the events loop of `proxy`. Fails if
+ * another daemon is listening already.
+ */
+pub fn listen(proxy: EventsLoopProxy) -> io::Result<Receiver<Request>> {
+    check_socket_dir(true)?;
+    let path = socket_path();
+    if UnixStream::connect(&path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("A spacerun daemon is listening on {} already", path.display()),
+        ));
+    }
+    // Left behind by a daemon that did not exit cleanly
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    eprintln!("Listening on {}", path.display());
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("Could not accept a connection: {}", err);
+                    continue;
+                }
+            };
+            let sender = sender.clone();
+            let proxy = proxy.clone();
+            thread::spawn(move || {
+                if let Err(err) = handle_connection(&stream, &sender, &proxy) {
+                    eprintln!("Connection failed: {}", err);
+                }
+            });
+        }
+    });
+    Ok(receiver)
+}
+
+/// Answer the requests of a client, one per line, until it disconnects.
+fn handle_connection(
+    stream: &UnixStream,
+    sender: &Sender<Request>,
+    proxy: &EventsLoopProxy,
+) -> io::Result<()> {
+    let mut writer = stream;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = respond(&line, sender, proxy) {
+            writer.write_all(format!("{}\n", response).as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// The response to the request `line`, `None` for notifications.
+fn respond(line: &str, sender: &Sender<Request>, proxy: &EventsLoopProxy) -> Option<Value> {
+    let request = match serde_json::from_str::<Value>(line) {
+        Ok(request) => request,
+        Err(err) => {
+            let err = RpcError::new(RpcError::PARSE_ERROR, err.to_string());
+            return Some(response(Value::Null, Err(err)));
+        }
+    };
+    let request: RawRequest = match serde_json::from_value(request) {
+        Ok(request) => request,
+        Err(err) => {
+            let err = RpcError::new(RpcError::INVALID_REQUEST, err.to_string());
+            return Some(response(Value::Null, Err(err)));
+        }
+    };
+    let result = if request.jsonrpc != JSONRPC_VERSION {
+        let message = format!("Only JSON-RPC {} is supported", JSONRPC_VERSION);
+        Err(RpcError::new(RpcError::INVALID_REQUEST, message))
+    } else {
+        Call::from_request(&request.method, &request.params)
+            .and_then(|call| dispatch(call, sender, proxy))
+    };
+    request.id.map(|id| response(id, result))
+}
+
+/// Hand `call` to the window and wait for its result.
+fn dispatch(
+    call: Call,
+    sender: &Sender<Request>,
+    proxy: &EventsLoopProxy,
+) -> Result<Value, RpcError> {
+    let closed = || RpcError::call_failed("spacerun is shutting down");
+    let (reply, result) = mpsc::channel();
+    sender.send(Request { call, reply }).map_err(|_| closed())?;
+    proxy.wakeup().map_err(|_| closed())?;
+    result.recv().map_err(|_| closed())?
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": JSONRPC_VERSION, "result": result, "id": id }),
+        Err(err) => json!({
+            "jsonrpc": JSONRPC_VERSION,
+            "error": { "code": err.code, "message": err.message },
+            "id": id,
+        }),
+    }
+}
+
+/**
+ * Carry out `call` on the window of the daemon.
+ *
+ * Calls without a result of their own return `true`.
+ */
+pub fn handle_call(call: Call, state: &mut State, display: &GliumDisplay) -> Result<Value, RpcError> {
+    match call {
+        Call::Show(path) => {
+            state.navigator.open(&path).map_err(RpcError::call_failed)?;
+            let height = state.window_dimensions.height;
+            update_window_and_window_state(height, state, display, true);
+            display.gl_window().show();
+            state.window_visible = true;
+            Ok(Value::Bool(true))
+        }
+        Call::Hide => {
+            display.gl_window().hide();
+            state.window_visible = false;
+            state.reset();
+            Ok(Value::Bool(true))
+        }
+        Call::Exec(path) => {
+            let leaf = exec::find_leaf(&state.config, &path).map_err(RpcError::call_failed)?;
+            // Both need the window, which `exec` leaves alone
+            if leaf.output == Some(Output::Show) || leaf.then.is_some() {
+                let message = format!(
+                    "`{}` shows its output or a menu afterwards, run it from the window with `show`",
+                    leaf.name
+                );
+                return Err(RpcError::call_failed(message));
+            }
+            exec::run_in_background(&leaf, &state.last_run).map_err(RpcError::call_failed)?;
+            Ok(Value::Bool(true))
+        }
+        Call::Reload => {
+            let config = config::load_config(&state.options);
+            state.reload_config(config).map_err(RpcError::call_failed)?;
+            Ok(Value::Bool(true))
+        }
+        Call::List(path) => {
+            let root = &state.config.commands;
+            let menus = navigator::resolve_menu(root, &path).map_err(RpcError::call_failed)?;
+            let menu = menus.last().cloned().unwrap_or(root);
+            let entries = match menu {
+                Command::Node(node) => node.children.iter().map(describe_command).collect(),
+                Command::Leaf(_) => vec![],
+            };
+            Ok(Value::Array(entries))
+        }
+        Call::Status => {
+            let path = state.navigator.path().iter().flat_map(|menu| menu.shortcut().0.clone());
+            Ok(json!({
+                "visible": state.window_visible,
+                "path": ShortcutPath(path.collect()).to_string(),
+                "menu": state.navigator.current().name(),
+                "config_files": state.config.sources,
+                "notice": state.notice.as_ref().map(|notice| &notice.text),
+            }))
+        }
+        Call::LastResult => {
+            let last_run = state.last_run.lock().unwrap();
+            Ok(match &*last_run {
+                None => Value::Null,
+                Some(run) => {
+                    let (status, exit_code, error) = match &run.status {
+                        RunStatus::Running => ("running", None, None),
+                        RunStatus::Exited(exit_code) => ("exited", *exit_code, None),
+                        RunStatus::Failed(err) => ("failed", None, Some(err)),
+                    };
+                    json!({
+                        "name": run.name,
+                        "cmd": run.cmd,
+                        "pid": run.pid,
+                        "status": status,
+                        "exit_code": exit_code,
+                        "error": error,
+                    })
+                }
+            })
+        }
+    }
+}
+
+fn describe_command(command: &Command) -> Value {
+    match command {
+        Command::Node(node) => json!({
+            "shortcut": node.shortcut.to_string(),
+            "name": node.name,
+            "menu": true,
+            "cmd": node.action.as_ref().map(|action| action.to_string()),
+        }),
+        Command::Leaf(leaf) => json!({
+            "shortcut": leaf.shortcut.to_string(),
+            "name": leaf.name,
+            "menu": false,
+            "cmd": leaf.action.to_string(),
+        }),
+    }
+}
+
+/**
+ * Call `method` of the running daemon.
+ *
+ * Returns the result of the call, or the error message of the daemon.
+ */
+pub fn call(method: &str, params: Value) -> Result<Value, String> {
+    let path = socket_path();
+    let connected = check_socket_dir(false).and_then(|_| UnixStream::connect(&path));
+    let mut stream = connected.map_err(|err| {
+        format!(
+            "No spacerun daemon is listening on {} ({}), start one with `spacerun daemon`",
+            path.display(),
+            err
+        )
+    })?;
+    let request = json!({
+        "jsonrpc": JSONRPC_VERSION,
+        "method": method,
+        "params": params,
+        "id": 1,
+    });
+    stream
+        .write_all(format!("{}\n", request).as_bytes())
+        .map_err(|err| err.to_string())?;
+
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .map_err(|err| err.to_string())?;
+    let mut response: Value = serde_json::from_str(&line)
+        .map_err(|err| format!("Invalid response of the daemon: {}", err))?;
+    match response.get("error") {
+        Some(error) => Err(error
+            .get("message")
+            .and_then(Value::as_str)
+            .unwrap_or("Unknown error")
+            .to_owned()),
+        None => Ok(response["result"].take()),
+    }
+}
@@ -0,0 +1,116 @@
+use std::env;
+use std::io::{self, prelude::*};
+
+use crate::action::Streams;
+use crate::bindings::{Shortcut, ShortcutPath};
+use crate::commands::CommandLeaf;
+use crate::config::SpacerunConfig;
+use crate::navigator::{Navigator, Outcome};
+use crate::output::Output;
+use crate::state;
+use crate::Options;
+
+/// Set by rofi when it runs spacerun as a `-modi` script.
+static ROFI_ENV: &str = "ROFI_RETV";
+
+/**
+ * Print the entries of a menu for dmenu or rofi, or carry out the entry
+ * picked from them.
+ *
+ * Entries are printed as `shortcut<TAB>name`, where the shortcut is the
+ * path from the menu given with `--path`. Picking a menu prints its entries,
+ * picking a command starts it and prints nothing. The entry is passed as an
+ * argument, as rofi does. Stdin is left alone, as launchers running
+ * spacerun often leave it open without ever writing to it.
+ *
+ * Returns the exit code spacerun should exit with.
+ */
+pub fn run(config: &SpacerunConfig, options: &Options) -> i32 {
+    let mut navigator = Navigator::new(config);
+    if let Err(err) = navigator.open(&state::initial_path(options)) {
+        eprintln!("{}", err);
+        return 1;
+    }
+
+    let path = match options.selection.as_ref().map(|selection| selected_path(selection)) {
+        None => vec![],
+        Some(Ok(path)) => path,
+        Some(Err(err)) => {
+            eprintln!("{}", err);
+            return 1;
+        }
+    };
+
+    for (index, shortcut) in path.iter().enumerate() {
+        match navigator.press(shortcut) {
+            Outcome::Entered | Outcome::Pending => {}
+            Outcome::Run(leaf) if index + 1 == path.len() => return start(&leaf),
+            Outcome::Run(leaf) => {
+                eprintln!("`{}` is a command, not a menu", leaf.name);
+                return 1;
+            }
+            Outcome::StickyToggled | Outcome::Unbound => {
+                eprintln!("Nothing is bound to `{}` in {}", shortcut, navigator.breadcrumbs());
+                return 1;
+            }
+        }
+    }
+    if !navigator.pending().is_empty() {
+        eprintln!("`{}` only starts a key sequence", ShortcutPath(path));
+        return 1;
+    }
+
+    if let Err(err) = print_menu(&navigator, &path) {
+        eprintln!("Could not print the menu: {}", err);
+        return 1;
+    }
+    0
+}
+
+/// The shortcuts of a printed entry, or of a path typed into the menu.
+fn selected_path(selection: &str) -> Result<Vec<Shortcut>, String> {
+    let shortcuts = selection.split('\t').next().unwrap_or_default();
+    shortcuts
+        .parse::<ShortcutPath>()
+        .map(|path| path.0)
+        .map_err(|err| format!("Invalid selection `{}`: {}", selection.trim(), err))
+}
+
+/// Print the entries of the current menu, each prefixed by `path`.
+fn print_menu(navigator: &Navigator, path: &[Shortcut]) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    if env::var_os(ROFI_ENV).is_some() {
+        writeln!(stdout, "\0prompt\x1f{}", navigator.breadcrumbs())?;
+    }
+    for child in navigator.entries() {
+        let mut shortcuts = path.to_vec();
+        shortcuts.extend(child.shortcut.0);
+        writeln!(stdout, "{}\t{}", ShortcutPath(shortcuts), child.name)?;
+    }
+    stdout.flush()
+}
+
+/**
+ * Start `leaf` detached from spacerun's output.
+ *
+ * dmenu pipelines and rofi read the output until it is closed, so a command
+ * still writing to it would keep them waiting. Commands showing their output
+ * or a menu afterwards are refused, as there is no window to show them in.
+ */
+fn start(leaf: &CommandLeaf) -> i32 {
+    if leaf.output == Some(Output::Show) || leaf.then.is_some() {
+        eprintln!(
+            "`{}` shows its output or a menu afterwards, run it from the window instead",
+            leaf.name
+        );
+        return 1;
+    }
+    match leaf.spawn(Streams::Detached) {
+        Ok(_) => 0,
+        Err(err) => {
+            eprintln!("Could not run `{}`: {}", leaf.action, err);
+            1
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::process::{Child, Command as CliCommand};
+
+/**
+ * Open `file` in `$VISUAL` or `$EDITOR` in the terminal spacerun runs in, or
+ * with `xdg-open` if no editor is set.
+ *
+ * The editor is run by the shell, so it may contain arguments like `code -w`.
+ * It gets `+line` to jump to `line` if known.
+ */
+pub fn open_in_editor(file: &Path, line: Option<usize>) -> io::Result<Child> {
+    match env_var("VISUAL").or_else(|| env_var("EDITOR")) {
+        Some(editor) => CliCommand::new("sh").args(editor_args(&editor, file, line)).spawn(),
+        None => CliCommand::new("xdg-open").arg(file).spawn(),
+    }
+}
+
+/**
+ * Open `file` from the window, which has no terminal for the editor.
+ *
+ * `$VISUAL` is run as it is, as it names a graphical editor. `$EDITOR` is
+ * run in `$TERMINAL` with `-e`. Without either, `xdg-open` opens the file.
+ */
+pub fn open_in_window_editor(file: &Path, line: Option<usize>) -> io::Result<Child> {
+    if let Some(editor) = env_var("VISUAL") {
+        return CliCommand::new("sh").args(editor_args(&editor, file, line)).spawn();
+    }
+    match (env_var("EDITOR"), env_var("TERMINAL")) {
+        (Some(editor), Some(terminal)) => CliCommand::new(terminal)
+            .arg("-e")
+            .arg("sh")
+            .args(editor_args(&editor, file, line))
+            .spawn(),
+        _ => CliCommand::new("xdg-open").arg(file).spawn(),
+    }
+}
+
+/// The arguments of `sh` running `editor` to open `file` at `line`.
+fn editor_args(editor: &str, file: &Path, line: Option<usize>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["-c".into()];
+    match line {
+        Some(line) => {
+            args.push(format!("{} \"$1\" \"$2\"", editor).into());
+            args.push("sh".into());
+            args.push(format!("+{}", line).into());
+        }
+        None => {
+            args.push(format!("{} \"$1\"", editor).into());
+            args.push("sh".into());
+        }
+    }
+    args.push(file.into());
+    args
+}
+
+/// The variable `name`, missing if it is not set or empty.
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
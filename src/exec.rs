@@ -0,0 +1,147 @@
+use std::fmt::{self, Display};
+use std::process::Child;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::action::Streams;
+use crate::bindings::Shortcut;
+use crate::commands::{Command, CommandLeaf};
+use crate::config::SpacerunConfig;
+
+/**
+ * Run the command at the end of `path` without opening a window.
+ *
+ * Waits for the command to finish and returns the exit code spacerun should
+ * exit with.
+ */
+pub fn exec(config: &SpacerunConfig, path: &[Shortcut]) -> i32 {
+    let leaf = match find_leaf(config, path) {
+        Ok(leaf) => leaf,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 1;
+        }
+    };
+
+    match leaf.spawn(Streams::Inherited).and_then(|mut child| child.wait()) {
+        Ok(status) => status.code().unwrap_or(1),
+        Err(err) => {
+            eprintln!("Could not run `{}`: {}", leaf.action, err);
+            1
+        }
+    }
+}
+
+/// The command at the end of `path`, which must not be a menu without a `cmd`.
+pub fn find_leaf(config: &SpacerunConfig, path: &[Shortcut]) -> Result<CommandLeaf, String> {
+    let command = config
+        .commands
+        .resolve_path(path)
+        .map_err(|err| err.to_string())?
+        .last()
+        .cloned()
+        .unwrap_or(&config.commands);
+
+    match command {
+        Command::Leaf(leaf) => Ok(leaf.clone()),
+        Command::Node(node) => node.cmd_leaf().ok_or_else(|| {
+            format!("`{}` opens a menu, there is no command to run", node.name)
+        }),
+    }
+}
+
+/**
+ * A command spacerun started
+ */
+#[derive(Debug, Clone)]
+pub struct CommandRun {
+    pub name: String,
+    pub cmd: String,
+    /// Process id, missing if the command could not be started
+    pub pid: Option<u32>,
+    pub status: RunStatus,
+}
+
+#[derive(Debug, Clone)]
+pub enum RunStatus {
+    Running,
+    /// Exit code of the command, missing if it was killed by a signal
+    Exited(Option<i32>),
+    Failed(String),
+}
+
+impl Display for RunStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunStatus::Running => write!(f, "running"),
+            RunStatus::Exited(Some(code)) => write!(f, "exit code {}", code),
+            RunStatus::Exited(None) => write!(f, "killed by a signal"),
+            RunStatus::Failed(err) => write!(f, "failed: {}", err),
+        }
+    }
+}
+
+/// The last command started, updated once it finishes.
+pub type LastRun = Arc<Mutex<Option<CommandRun>>>;
+
+/**
+ * Start `leaf` without waiting for it to finish.
+ *
+ * The run is recorded in `last_run`, whose status is updated by a thread
+ * waiting for the command.
+ */
+pub fn run_in_background(leaf: &CommandLeaf, last_run: &LastRun) -> Result<(), String> {
+    let child = start(leaf, Streams::Inherited, last_run)?;
+    wait_in_background(child, last_run, |_| {});
+    Ok(())
+}
+
+/// Start `leaf` and record it as the last run, also if it fails to start.
+pub fn start(leaf: &CommandLeaf, streams: Streams, last_run: &LastRun) -> Result<Child, String> {
+    let mut run = CommandRun {
+        name: leaf.name.clone(),
+        cmd: leaf.action.to_string(),
+        pid: None,
+        status: RunStatus::Running,
+    };
+    match leaf.spawn(streams) {
+        Ok(child) => {
+            run.pid = Some(child.id());
+            *last_run.lock().unwrap() = Some(run);
+            Ok(child)
+        }
+        Err(err) => {
+            let message = format!("Could not run `{}`: {}", leaf.action, err);
+            run.status = RunStatus::Failed(message.clone());
+            *last_run.lock().unwrap() = Some(run);
+            Err(message)
+        }
+    }
+}
+
+/**
+ * Wait for `child` in a thread, updating its status in `last_run` once it
+ * finished.
+ *
+ * `finished` is called with the status afterwards.
+ */
+pub fn wait_in_background<F>(mut child: Child, last_run: &LastRun, finished: F)
+where
+    F: FnOnce(RunStatus) + Send + 'static,
+{
+    let pid = child.id();
+    let last_run = last_run.clone();
+    thread::spawn(move || {
+        let status = match child.wait() {
+            Ok(status) => RunStatus::Exited(status.code()),
+            Err(err) => RunStatus::Failed(err.to_string()),
+        };
+        if let Some(run) = last_run.lock().unwrap().as_mut() {
+            // Unless another command was started in the meantime
+            if run.pid == Some(pid) {
+                run.status = status.clone();
+            }
+        }
+        finished(status);
+    });
+}
@@ -0,0 +1,531 @@
+//! A reader for the subset of [KDL](https://kdl.dev) documents spacerun's
+//! config needs, turning them into the same `Value` tree a JSON config gives.
+//!
+//! Nodes map to values like this:
+//!
+//! * `font_size 14` is the field `"font_size": 14`. A node with several
+//!   arguments becomes a list.
+//! * A node with properties or children becomes an object of its properties
+//!   and child nodes, e.g. `commands shortcut="r" name="Root" { ... }`.
+//! * A node whose children are all named `-` becomes a list, so
+//!   `children { - shortcut="f" name="firefox" cmd="firefox" }` is a list of
+//!   one command. The same goes for a document of only `-` nodes.
+//! * Fields the config expects a list in are lists even with a single
+//!   argument or an empty block, e.g. `children {}` or `exec "htop"`.
+//!
+//! Only strings, numbers, booleans, `null` and `//` and `/* */` comments are
+//! supported beyond that.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+use serde_json::{Map, Number, Value};
+
+use crate::locations::field_path;
+
+static LIST_ITEM_NAME: &str = "-";
+/// Nodes that are read as a list, whatever their arguments or children are
+static LIST_FIELDS: &[&str] = &["children", "exec"];
+
+#[derive(Debug, Clone)]
+pub struct KdlError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for KdlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {} column {}", self.message, self.line, self.column)
+    }
+}
+
+/// Parse a KDL document into a `Value`.
+pub fn from_str(input: &str) -> Result<Value, KdlError> {
+    parse(input).map(|(value, _)| value)
+}
+
+/**
+ * Byte offsets of the nodes of a KDL document, keyed by the path of their
+ * value, e.g. `commands.children[0].shortcut`.
+ *
+ * Properties are located at the node they belong to.
+ */
+pub fn node_offsets(input: &str) -> Result<HashMap<String, usize>, KdlError> {
+    parse(input).map(|(_, offsets)| offsets)
+}
+
+fn parse(input: &str) -> Result<(Value, HashMap<String, usize>), KdlError> {
+    let mut parser = Parser { input, position: 0 };
+    let nodes = parser.nodes(false)?;
+    let mut offsets = HashMap::new();
+    let value = nodes_to_value(nodes, "", &mut offsets)
+        .map_err(|(position, message)| parser.error_at(position, message))?;
+    Ok((value, offsets))
+}
+
+struct Node {
+    name: String,
+    position: usize,
+    arguments: Vec<Value>,
+    properties: Map<String, Value>,
+    children: Option<Vec<Node>>,
+}
+
+/// Turn the nodes of a document or children block at `path` into an object
+/// or list.
+fn nodes_to_value(
+    nodes: Vec<Node>,
+    path: &str,
+    offsets: &mut HashMap<String, usize>,
+) -> Result<Value, (usize, String)> {
+    if !nodes.is_empty() && nodes.iter().all(|node| node.name == LIST_ITEM_NAME) {
+        return nodes
+            .into_iter()
+            .enumerate()
+            .map(|(index, node)| node_to_value(node, &format!("{}[{}]", path, index), offsets))
+            .collect::<Result<Vec<Value>, _>>()
+            .map(Value::Array);
+    }
+
+    let mut object = Map::new();
+    for node in nodes {
+        if node.name == LIST_ITEM_NAME {
+            return Err((
+                node.position,
+                "list items (`-`) cannot be mixed with named nodes".into(),
+            ));
+        }
+        if object.contains_key(&node.name) {
+            return Err((node.position, format!("duplicate node `{}`", node.name)));
+        }
+        let name = node.name.clone();
+        let path = field_path(path, &name);
+        let value = if LIST_FIELDS.contains(&name.as_str()) {
+            node_to_list(node, &path, offsets)?
+        } else {
+            node_to_value(node, &path, offsets)?
+        };
+        object.insert(name, value);
+    }
+    Ok(Value::Object(object))
+}
+
+fn node_to_value(
+    node: Node,
+    path: &str,
+    offsets: &mut HashMap<String, usize>,
+) -> Result<Value, (usize, String)> {
+    let Node {
+        position,
+        mut arguments,
+        properties,
+        children,
+        ..
+    } = node;
+    offsets.insert(path.to_owned(), position);
+    for key in properties.keys() {
+        offsets.insert(field_path(path, key), position);
+    }
+
+    if properties.is_empty() && children.is_none() {
+        return Ok(match arguments.len() {
+            0 => Value::Null,
+            1 => arguments.remove(0),
+            _ => Value::Array(arguments),
+        });
+    }
+    if !arguments.is_empty() {
+        return Err((
+            position,
+            "a node with properties or children cannot have arguments".into(),
+        ));
+    }
+
+    match children
+        .map(|children| nodes_to_value(children, path, offsets))
+        .transpose()?
+    {
+        Some(Value::Array(items)) => {
+            if properties.is_empty() {
+                Ok(Value::Array(items))
+            } else {
+                Err((position, "a list cannot have properties".into()))
+            }
+        }
+        Some(Value::Object(fields)) => {
+            let mut object = properties;
+            for (name, value) in fields {
+                if object.contains_key(&name) {
+                    return Err((position, format!("`{}` is set twice", name)));
+                }
+                object.insert(name, value);
+            }
+            Ok(Value::Object(object))
+        }
+        _ => Ok(Value::Object(properties)),
+    }
+}
+
+/// Read a node of `LIST_FIELDS` at `path`, from its arguments or its `-`
+/// children.
+fn node_to_list(
+    node: Node,
+    path: &str,
+    offsets: &mut HashMap<String, usize>,
+) -> Result<Value, (usize, String)> {
+    offsets.insert(path.to_owned(), node.position);
+    if !node.properties.is_empty() {
+        return Err((node.position, "a list cannot have properties".into()));
+    }
+    match node.children {
+        Some(_) if !node.arguments.is_empty() => Err((
+            node.position,
+            "a list is either written as arguments or as children".into(),
+        )),
+        Some(children) if children.is_empty() => Ok(Value::Array(vec![])),
+        Some(children) => match nodes_to_value(children, path, offsets)? {
+            list @ Value::Array(_) => Ok(list),
+            _ => Err((
+                node.position,
+                format!("the items of `{}` have to be named `-`", node.name),
+            )),
+        },
+        None => Ok(Value::Array(node.arguments)),
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn error_at<M: Into<String>>(&self, position: usize, message: M) -> KdlError {
+        let before = &self.input[..position];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rfind('\n')
+            .map_or(before, |newline| &before[newline + 1..])
+            .chars()
+            .count()
+            + 1;
+        KdlError {
+            message: message.into(),
+            line,
+            column,
+        }
+    }
+
+    fn error<M: Into<String>>(&self, message: M) -> KdlError {
+        self.error_at(self.position, message)
+    }
+
+    /// Nodes up to the end of the input, or up to the `}` of a children block.
+    fn nodes(&mut self, in_block: bool) -> Result<Vec<Node>, KdlError> {
+        let mut nodes = vec![];
+        loop {
+            self.skip_line_space()?;
+            match self.peek() {
+                None if in_block => return Err(self.error("missing `}`")),
+                None => return Ok(nodes),
+                Some('}') if in_block => {
+                    self.bump();
+                    return Ok(nodes);
+                }
+                Some('}') => return Err(self.error("unexpected `}`")),
+                _ => nodes.push(self.node()?),
+            }
+        }
+    }
+
+    fn node(&mut self) -> Result<Node, KdlError> {
+        let position = self.position;
+        let name = self.identifier()?;
+        let mut node = Node {
+            name,
+            position,
+            arguments: vec![],
+            properties: Map::new(),
+            children: None,
+        };
+
+        loop {
+            let had_space = self.skip_node_space()?;
+            match self.peek() {
+                None | Some('\n') | Some('\r') | Some(';') => {
+                    self.bump();
+                    return Ok(node);
+                }
+                Some('}') => return Ok(node),
+                Some('{') => {
+                    self.bump();
+                    node.children = Some(self.nodes(true)?);
+                    return self.node_end(node);
+                }
+                _ if !had_space => return Err(self.error("expected a space between entries")),
+                _ => {}
+            }
+
+            let start = self.position;
+            if self.starts_identifier() {
+                let key = self.identifier()?;
+                if self.peek() == Some('=') {
+                    self.bump();
+                    let value = self.value()?;
+                    node.properties.insert(key, value);
+                    continue;
+                }
+                self.position = start;
+            }
+            let value = self.value()?;
+            node.arguments.push(value);
+        }
+    }
+
+    /// After a children block only a node terminator may follow.
+    fn node_end(&mut self, node: Node) -> Result<Node, KdlError> {
+        self.skip_node_space()?;
+        match self.peek() {
+            None | Some('}') => Ok(node),
+            Some('\n') | Some('\r') | Some(';') => {
+                self.bump();
+                Ok(node)
+            }
+            _ => Err(self.error("expected the end of the node after its children")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, KdlError> {
+        match self.peek() {
+            Some('"') => self.string().map(Value::String),
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' => self.number(),
+            _ => {
+                let start = self.position;
+                match self.bare_word() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "null" => Ok(Value::Null),
+                    _ => Err(self.error_at(start, "expected a value")),
+                }
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, KdlError> {
+        let start = self.position;
+        let word = self.bare_word().replace('_', "");
+        let number = match word.parse::<i64>() {
+            Ok(number) => Some(Number::from(number)),
+            Err(_) => word.parse::<f64>().ok().and_then(Number::from_f64),
+        };
+        number
+            .map(Value::Number)
+            .ok_or_else(|| self.error_at(start, format!("invalid number `{}`", word)))
+    }
+
+    fn string(&mut self) -> Result<String, KdlError> {
+        let start = self.position;
+        self.bump();
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error_at(start, "unterminated string")),
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let escaped = match self.bump() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    string.push(escaped);
+                }
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn starts_identifier(&self) -> bool {
+        match self.peek() {
+            Some('"') => true,
+            Some(c) => is_identifier_char(c) && !c.is_ascii_digit(),
+            None => false,
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, KdlError> {
+        match self.peek() {
+            Some('"') => self.string(),
+            _ if self.starts_identifier() => Ok(self.bare_word().to_owned()),
+            _ => Err(self.error("expected a node name")),
+        }
+    }
+
+    fn bare_word(&mut self) -> &'a str {
+        let rest = self.rest();
+        let end = rest.find(|c| !is_identifier_char(c)).unwrap_or(rest.len());
+        self.position += end;
+        &rest[..end]
+    }
+
+    /// Skip whitespace, newlines and comments between nodes.
+    fn skip_line_space(&mut self) -> Result<(), KdlError> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() || c == ';' => {
+                    self.bump();
+                }
+                _ if self.rest().starts_with("//") => self.skip_line_comment(),
+                _ if self.rest().starts_with("/*") => self.skip_block_comment()?,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Skip whitespace and comments inside a node.
+    ///
+    /// Stops at newlines as they end the node. Returns if anything was
+    /// skipped.
+    fn skip_node_space(&mut self) -> Result<bool, KdlError> {
+        let start = self.position;
+        loop {
+            match self.peek() {
+                Some('\n') | Some('\r') => break,
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                _ if self.rest().starts_with("//") => {
+                    self.skip_line_comment();
+                    break;
+                }
+                _ if self.rest().starts_with("/*") => self.skip_block_comment()?,
+                _ => break,
+            }
+        }
+        Ok(self.position != start)
+    }
+
+    fn skip_line_comment(&mut self) {
+        let end = self.rest().find('\n').unwrap_or(self.rest().len());
+        self.position += end;
+    }
+
+    fn skip_block_comment(&mut self) -> Result<(), KdlError> {
+        match self.rest().find("*/") {
+            Some(end) => {
+                self.position += end + 2;
+                Ok(())
+            }
+            None => Err(self.error("unterminated comment")),
+        }
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    !c.is_whitespace() && !"\\/(){}<>;[]=,\"".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn nodes_become_fields() {
+        let value = from_str(
+            "font_size 14 // points\n\
+             /* the\n   position */ position \"center\"\n\
+             exec \"ls\"; args \"ls\" \"-l\"; enabled true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "font_size": 14,
+                "position": "center",
+                "exec": ["ls"],
+                "args": ["ls", "-l"],
+                "enabled": true,
+            })
+        );
+    }
+
+    #[test]
+    fn children_are_lists_of_commands() {
+        let value = from_str(
+            "commands shortcut=\"r\" name=\"Root\" {\n\
+                 children {\n\
+                     - shortcut=\"f\" name=\"firefox\" cmd=\"firefox\"\n\
+                     - shortcut=\"g\" name=\"Git\" {\n\
+                         children {}\n\
+                     }\n\
+                 }\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "commands": {
+                    "shortcut": "r",
+                    "name": "Root",
+                    "children": [
+                        {"shortcut": "f", "name": "firefox", "cmd": "firefox"},
+                        {"shortcut": "g", "name": "Git", "children": []},
+                    ],
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn document_of_list_items_is_a_list() {
+        let value = from_str("- shortcut=\"a\" cmd=\"a\"\n- shortcut=\"b\" cmd=\"b\"").unwrap();
+        assert_eq!(
+            value,
+            json!([{"shortcut": "a", "cmd": "a"}, {"shortcut": "b", "cmd": "b"}])
+        );
+    }
+
+    #[test]
+    fn strings_and_numbers() {
+        let value = from_str("s \"a \\\"b\\\"\\n\"\nf -1.5\ni 1_000\nn null").unwrap();
+        assert_eq!(
+            value,
+            json!({"s": "a \"b\"\n", "f": -1.5, "i": 1000, "n": null})
+        );
+    }
+
+    #[test]
+    fn errors_have_locations() {
+        let err = from_str("a 1\nb {\n  - x=1\n  c 2\n}\n").unwrap_err();
+        assert_eq!((err.line, err.column), (3, 3));
+        assert!(err.message.contains("cannot be mixed"));
+
+        let err = from_str("a 1\na 2").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+
+        let err = from_str("a \"open").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 3));
+
+        let err = from_str("a {\n  b 1\n").unwrap_err();
+        assert!(err.message.contains("missing `}`"));
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use toml::Spanned;
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::Marker;
+
+use crate::config;
+use crate::config_format::{ConfigFormat, Location};
+use crate::kdl;
+
+/**
+ * Locations of the values of a config file, keyed by their path, e.g.
+ * `commands.children[3].shortcut`.
+ */
+pub type Locations = HashMap<String, Location>;
+
+/**
+ * Find out where the values of the config file `contents` are.
+ *
+ * Fields of YAML and TOML files are located at their key.
+ */
+pub fn locate(format: ConfigFormat, contents: &str) -> Locations {
+    let offsets = match format {
+        ConfigFormat::Json => {
+            let mut scanner = JsonScanner {
+                input: contents,
+                position: 0,
+                offsets: HashMap::new(),
+            };
+            scanner.value(String::new());
+            scanner.offsets
+        }
+        ConfigFormat::Kdl => kdl::node_offsets(contents).unwrap_or_default(),
+        ConfigFormat::Toml => toml::from_str::<TomlValue>(contents)
+            .map(|root| {
+                let mut offsets = HashMap::new();
+                root.offsets(String::new(), Some(0), &mut offsets);
+                offsets
+            })
+            .unwrap_or_default(),
+        // YAML counts characters instead of bytes, so it gives locations directly
+        ConfigFormat::Yaml => {
+            let mut scanner = YamlScanner {
+                containers: vec![],
+                locations: HashMap::new(),
+            };
+            // Stops at the first syntax error, reporting those is up to serde_yaml
+            let _ = Parser::new(contents.chars()).load(&mut scanner, false);
+            return scanner.locations;
+        }
+    };
+    offsets
+        .into_iter()
+        .map(|(path, offset)| (path, Location::at_offset(contents, offset)))
+        .collect()
+}
+
+/// The location of `path` in the config file `file`, read from disk.
+pub fn locate_in_file(file: &Path, path: &str) -> Option<Location> {
+    let (format, contents) = config::read_config_file(file).ok()?;
+    closest_location(&locate(format, &contents), path)
+}
+
+/// The path of the field `field` of the value at `path`.
+pub fn field_path(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_owned()
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+/// The location of `path`, or of the closest parent that has one.
+pub fn closest_location(locations: &Locations, path: &str) -> Option<Location> {
+    let mut path = path;
+    loop {
+        if let Some(location) = locations.get(path) {
+            return Some(*location);
+        }
+        path = &path[..path.rfind(['.', '['])?];
+    }
+}
+
+/**
+ * Records where each value of a well-formed JSON document starts.
+ *
+ * Stops at the first syntax error, reporting those is up to serde_json.
+ */
+struct JsonScanner<'a> {
+    input: &'a str,
+    position: usize,
+    offsets: HashMap<String, usize>,
+}
+
+impl<'a> JsonScanner<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.position).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.skip_whitespace();
+        if self.peek()? == byte {
+            self.position += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn value(&mut self, path: String) -> Option<()> {
+        self.skip_whitespace();
+        self.offsets.insert(path.clone(), self.position);
+        match self.peek()? {
+            b'{' => {
+                self.position += 1;
+                self.skip_whitespace();
+                if self.peek()? == b'}' {
+                    self.position += 1;
+                    return Some(());
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    self.value(field_path(&path, &key))?;
+                    self.skip_whitespace();
+                    match self.peek()? {
+                        b',' => self.position += 1,
+                        b'}' => {
+                            self.position += 1;
+                            return Some(());
+                        }
+                        _ => return None,
+                    }
+                }
+            }
+            b'[' => {
+                self.position += 1;
+                self.skip_whitespace();
+                if self.peek()? == b']' {
+                    self.position += 1;
+                    return Some(());
+                }
+                let mut index = 0;
+                loop {
+                    self.value(format!("{}[{}]", path, index))?;
+                    index += 1;
+                    self.skip_whitespace();
+                    match self.peek()? {
+                        b',' => self.position += 1,
+                        b']' => {
+                            self.position += 1;
+                            return Some(());
+                        }
+                        _ => return None,
+                    }
+                }
+            }
+            b'"' => self.string().map(|_| ()),
+            _ => {
+                while let Some(byte) = self.peek() {
+                    match byte {
+                        b',' | b']' | b'}' | b' ' | b'\t' | b'\n' | b'\r' => break,
+                        _ => self.position += 1,
+                    }
+                }
+                Some(())
+            }
+        }
+    }
+
+    /// A string, returned with its escapes left as they are.
+    fn string(&mut self) -> Option<String> {
+        if self.peek()? != b'"' {
+            return None;
+        }
+        self.position += 1;
+        let start = self.position;
+        loop {
+            match self.peek()? {
+                b'"' => break,
+                b'\\' => self.position += 2,
+                _ => self.position += 1,
+            }
+        }
+        let string = self.input.get(start..self.position)?.to_owned();
+        self.position += 1;
+        Some(string)
+    }
+}
+
+/**
+ * Records where each value of a YAML document starts, from the events of
+ * its parser
+ *
+ * Mappings and sequences are located at their first key or item, the parser
+ * only knows where they start once it read that.
+ */
+struct YamlScanner {
+    /// The mappings and sequences the parser is in, the innermost one last
+    containers: Vec<YamlContainer>,
+    locations: Locations,
+}
+
+enum YamlContainer {
+    /// A mapping, with the key whose value comes next if it was read
+    Mapping {
+        path: String,
+        start: Location,
+        key: Option<String>,
+    },
+    /// A sequence, with the index of the item that comes next
+    Sequence {
+        path: String,
+        start: Location,
+        index: usize,
+    },
+}
+
+impl YamlScanner {
+    /**
+     * The path of the value starting at `location`.
+     *
+     * Missing if the value is the key of a mapping, `key` being its text.
+     * Fields are located at their key.
+     */
+    fn next_path(&mut self, key: Option<&str>, location: Location) -> Option<String> {
+        let key_path = match self.containers.last_mut() {
+            None => return Some(String::new()),
+            Some(YamlContainer::Mapping { path, key: next_key, .. }) => match next_key.take() {
+                Some(key) => return Some(field_path(path, &key)),
+                None => {
+                    let key = key.unwrap_or_default().to_owned();
+                    let key_path = field_path(path, &key);
+                    *next_key = Some(key);
+                    key_path
+                }
+            },
+            Some(YamlContainer::Sequence { path, index, .. }) => {
+                *index += 1;
+                return Some(format!("{}[{}]", path, *index - 1));
+            }
+        };
+        self.record(key_path, location);
+        None
+    }
+
+    /// Record `location` for `path` and the containers it is in that have none yet.
+    fn record(&mut self, path: String, location: Location) {
+        for container in &self.containers {
+            let container_path = match container {
+                YamlContainer::Mapping { path, .. } | YamlContainer::Sequence { path, .. } => path,
+            };
+            self.locations.entry(container_path.clone()).or_insert(location);
+        }
+        self.locations.entry(path).or_insert(location);
+    }
+}
+
+impl MarkedEventReceiver for YamlScanner {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        let location = Location {
+            line: mark.line(),
+            column: mark.col() + 1,
+        };
+        match event {
+            Event::Scalar(text, ..) => {
+                if let Some(path) = self.next_path(Some(&text), location) {
+                    self.record(path, location);
+                }
+            }
+            Event::Alias(_) => {
+                if let Some(path) = self.next_path(None, location) {
+                    self.record(path, location);
+                }
+            }
+            // Keys that are mappings or sequences themselves get a path no
+            // config value has
+            Event::MappingStart(_) => {
+                let path = self.next_path(None, location).unwrap_or_else(|| "?".into());
+                self.containers.push(YamlContainer::Mapping {
+                    path,
+                    start: location,
+                    key: None,
+                });
+            }
+            Event::SequenceStart(_) => {
+                let path = self.next_path(None, location).unwrap_or_else(|| "?".into());
+                self.containers.push(YamlContainer::Sequence {
+                    path,
+                    start: location,
+                    index: 0,
+                });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                // Empty ones are located where the parser saw them start
+                if let Some(YamlContainer::Mapping { path, start, .. })
+                | Some(YamlContainer::Sequence { path, start, .. }) = self.containers.pop()
+                {
+                    self.locations.entry(path).or_insert(start);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/**
+ * The shape of a TOML document, with where its keys and values are
+ *
+ * toml cannot tell where tables start, it gives them the span `0..0`.
+ */
+enum TomlValue {
+    Table(Vec<(Spanned<String>, Spanned<TomlValue>)>),
+    Array(Vec<Spanned<TomlValue>>),
+    Other,
+}
+
+impl TomlValue {
+    /**
+     * Record the offsets of this value at `path` and of the values inside it.
+     *
+     * A value without an `offset` of its own is where its first field is.
+     */
+    fn offsets(
+        &self,
+        path: String,
+        offset: Option<usize>,
+        offsets: &mut HashMap<String, usize>,
+    ) -> Option<usize> {
+        let first_offset = match self {
+            // Fields are located at their key
+            TomlValue::Table(fields) => fields
+                .iter()
+                .filter_map(|(key, value)| {
+                    let path = field_path(&path, key.get_ref());
+                    value.get_ref().offsets(path, Some(key.start()), offsets)
+                })
+                .min(),
+            TomlValue::Array(items) => items
+                .iter()
+                .enumerate()
+                .filter_map(|(index, item)| {
+                    let offset = match (item.start(), item.end()) {
+                        (0, 0) => None,
+                        (start, _) => Some(start),
+                    };
+                    let path = format!("{}[{}]", path, index);
+                    item.get_ref().offsets(path, offset, offsets)
+                })
+                .min(),
+            TomlValue::Other => None,
+        };
+        let offset = offset.or(first_offset)?;
+        offsets.insert(path, offset);
+        Some(offset)
+    }
+}
+
+impl<'de> Deserialize<'de> for TomlValue {
+    fn deserialize<D>(deserializer: D) -> Result<TomlValue, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(TomlValueVisitor)
+    }
+}
+
+struct TomlValueVisitor;
+
+impl<'de> Visitor<'de> for TomlValueVisitor {
+    type Value = TomlValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a TOML value")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<TomlValue, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut fields = vec![];
+        while let Some(key) = map.next_key()? {
+            fields.push((key, map.next_value()?));
+        }
+        Ok(TomlValue::Table(fields))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<TomlValue, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut items = vec![];
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(TomlValue::Array(items))
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<TomlValue, E> {
+        Ok(TomlValue::Other)
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<TomlValue, E> {
+        Ok(TomlValue::Other)
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<TomlValue, E> {
+        Ok(TomlValue::Other)
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<TomlValue, E> {
+        Ok(TomlValue::Other)
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<TomlValue, E> {
+        Ok(TomlValue::Other)
+    }
+}
@@ -1,23 +1,50 @@
 use conrod::backend::glium::glium::glutin::os::unix::WindowBuilderExt;
+use conrod::backend::glium::glium::glutin::EventsLoopProxy;
 use conrod::backend::glium::glium::{self, Surface};
 use conrod::backend::glium::Renderer;
+use std::path::PathBuf;
+use serde_json::json;
+use std::process;
 use structopt::StructOpt;
 
-use crate::bindings::Shortcut;
+use crate::bindings::{KeyPress, Shortcut, ShortcutPath};
+use crate::config::ConfigError;
+use crate::daemon::Call;
 use crate::event_loop::EventLoop;
-use crate::state::State;
-use crate::view::SpacerunEvent::{CloseApplication, FocusLost, PrevLevelCommand, SelectCommand};
+use crate::navigator::Outcome;
+use crate::output::{CommandOutput, Output};
+use crate::reopen::Reopener;
+use crate::state::{Notice, State};
+use crate::view::SpacerunEvent::{
+    CharacterTyped, CloseApplication, DismissNotice, DismissOutput, EditNoticeFile, FocusLost,
+    KeyPressed, PrevLevelCommand, ScrollOutput,
+};
 use crate::view::{
     handle_event, rendered_elements_height, set_ui, update_initial_window_state,
     update_window_and_window_state, Ids,
 };
+use crate::watch::ConfigWatcher;
 
+mod action;
 mod bindings;
 mod commands;
 mod config;
+mod config_format;
+mod daemon;
+mod dmenu;
+mod editor;
 mod event_loop;
+mod exec;
+mod kdl;
+mod locations;
+mod navigator;
+mod output;
+mod reopen;
 mod state;
+mod tui;
+mod validate;
 mod view;
+mod watch;
 mod window_position;
 
 static FONT: &[u8] = include_bytes!("../assets/fonts/NotoSans/NotoSans-Regular.ttf");
@@ -25,8 +52,60 @@ static FONT: &[u8] = include_bytes!("../assets/fonts/NotoSans/NotoSans-Regular.t
 #[derive(Debug, StructOpt)]
 #[structopt(name = "spacerun")]
 pub struct Options {
+    /// Config file to use instead of searching the XDG config directories
+    #[structopt(short = "c", long = "config", parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// Reject fields of config files spacerun does not know
+    #[structopt(long = "strict")]
+    strict: bool,
     #[structopt(short = "s", long = "shortcut", parse(try_from_str))]
     initial_shortcut: Option<Shortcut>,
+    /// Open the menu reached by these shortcuts, e.g. "w n" or "C-x f"
+    #[structopt(
+        short = "p",
+        long = "path",
+        parse(try_from_str),
+        conflicts_with = "initial_shortcut"
+    )]
+    initial_path: Option<ShortcutPath>,
+    /// Show the menus in the terminal instead of a window
+    #[structopt(long = "tui")]
+    tui: bool,
+    /// Print the menu for dmenu or rofi and carry out the entry picked from it
+    #[structopt(long = "dmenu", conflicts_with = "tui")]
+    dmenu: bool,
+    /// The entry picked with --dmenu, prints the menu if missing
+    #[structopt(requires = "dmenu")]
+    selection: Option<String>,
+    #[structopt(subcommand)]
+    subcommand: Option<Subcommand>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Subcommand {
+    /// Run a command by its shortcut path without opening the window
+    #[structopt(name = "exec")]
+    Exec {
+        /// Shortcuts separated by spaces, e.g. "w n"
+        #[structopt(long = "path", parse(try_from_str), conflicts_with = "shortcuts")]
+        path: Option<ShortcutPath>,
+        /// Shortcuts leading to the command, e.g. `r w n`
+        #[structopt(parse(try_from_str))]
+        shortcuts: Vec<Shortcut>,
+    },
+    /// Check the config for problems and report them
+    #[structopt(name = "validate")]
+    Validate,
+    /// Keep running in the background and show the window on `spacerun show`
+    #[structopt(name = "daemon")]
+    Daemon,
+    /// Show the window of the running daemon
+    #[structopt(name = "show")]
+    Show {
+        /// Open the menu reached by these shortcuts, e.g. "w n"
+        #[structopt(long = "path", parse(try_from_str))]
+        path: Option<ShortcutPath>,
+    },
 }
 
 fn main() {
@@ -34,13 +113,62 @@ fn main() {
     let options = Options::from_args();
     eprintln!("options: {:?}", options);
 
+    if let Some(Subcommand::Validate) = options.subcommand {
+        process::exit(validate::validate(&options));
+    }
+    if let Some(Subcommand::Show { path }) = &options.subcommand {
+        let path = path.as_ref().map(ToString::to_string);
+        if let Err(err) = daemon::call("show", json!({ "path": path })) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        return;
+    }
+    let is_daemon = matches!(options.subcommand, Some(Subcommand::Daemon));
+    if is_daemon && options.tui {
+        eprintln!("The daemon only shows a window, it cannot be run with --tui");
+        process::exit(1);
+    }
+
     // --- Setup Commands
-    let config = config::load_config()
-        .expect("Error loading the config. Check your configuration for inconsistencies.");
-    eprintln!("Commands Loaded!");
-    eprintln!("{:?}", config.commands);
+    let mut config = config::load_config(&options);
+    let mut starter_config_path = None;
+    let opens_window = options.subcommand.is_none() || is_daemon;
+    if let (Err(ConfigError::NotFound(_)), true) = (&config, opens_window) {
+        config = config::write_starter_config().and_then(|path| {
+            starter_config_path = Some(path);
+            config::load_config(&options)
+        });
+    }
+    if let Err(err) = &config {
+        eprintln!("{}", err);
+    }
+
+    if let Some(Subcommand::Exec { path, shortcuts }) = &options.subcommand {
+        let config = config.unwrap_or_else(|_| process::exit(1));
+        let path = path.as_ref().map_or(shortcuts, |path| &path.0);
+        process::exit(exec::exec(&config, path));
+    }
+    if options.dmenu {
+        let config = config.unwrap_or_else(|_| process::exit(1));
+        process::exit(dmenu::run(&config, &options));
+    }
+
+    let mut state = match config {
+        Ok(config) => {
+            let mut state = State::new(config, options).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+            state.notice = starter_config_path.map(Notice::starter_config);
+            state
+        }
+        Err(err) => State::with_notice(options, Notice::config_error(&err)),
+    };
 
-    let mut state = State::new(config, options);
+    if state.options.tui {
+        process::exit(tui::run(state));
+    }
 
     // --- Setup Conrod UI
     let mut ui = conrod::UiBuilder::new([
@@ -77,15 +205,41 @@ fn main() {
     // The image map describing each of our widget->image mappings (in our case, none).
     let image_map = conrod::image::Map::<glium::texture::Texture2d>::new();
 
-    display.gl_window().show();
+    if !is_daemon {
+        display.gl_window().show();
+        state.window_visible = true;
+    }
 
     // WindowBuilder has no `with_position`, so we should update the window
     // with its dimensions directly after it was created.
     update_window_and_window_state(state.window_dimensions.height, &mut state, &display, true);
 
     let mut event_loop = EventLoop::new();
+    // Wakes up the events loop as the output of a command comes in
+    let output_proxy = events_loop.create_proxy();
+    let mut reopener = Reopener::new(events_loop.create_proxy());
+
+    let mut config_watcher = ConfigWatcher::new(events_loop.create_proxy())
+        .map_err(|err| eprintln!("Not watching the config for changes: {}", err))
+        .ok();
+    if let Some(config_watcher) = &mut config_watcher {
+        config_watcher.watch(watched_config_files(&state));
+    }
 
+    let daemon_requests = if is_daemon {
+        let requests = daemon::listen(events_loop.create_proxy()).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+        Some(requests)
+    } else {
+        None
+    };
+
+    // A key waiting for the character it typed, which may come with the next events
+    let mut pressed_key: Option<Shortcut> = None;
     'main: loop {
+        let mut close = false;
         // Handle all events.
         for event in event_loop.next(&mut events_loop) {
             // Use the `winit` backend feature to convert the winit event to a conrod one.
@@ -94,22 +248,34 @@ fn main() {
                 event_loop.needs_update();
             }
             match handle_event(&event, &state) {
-                Some(SelectCommand(new_selected_command)) => {
-                    state.selected_command = new_selected_command.clone();
-                    state
-                        .selection_path
-                        .push(state.selected_command.clone().into());
+                Some(KeyPressed(shortcut)) => {
+                    if let Some(key) = pressed_key.replace(shortcut) {
+                        close |= press_key(&mut state, &KeyPress::from(key), &output_proxy, &mut reopener);
+                    }
+                }
+                Some(CharacterTyped(character)) => {
+                    let key_press = KeyPress::typed(pressed_key.take(), character);
+                    close |= press_key(&mut state, &key_press, &output_proxy, &mut reopener);
                 }
                 Some(PrevLevelCommand) => {
-                    if state.selection_path.pop().is_some() {
-                        let new_command = state.selection_path.iter().try_fold(
-                            &state.config.commands,
-                            |acc, command_display| {
-                                acc.find_child_for_shortcut(&command_display.shortcut)
-                            },
-                        );
-                        if let Some(new_command) = new_command {
-                            state.selected_command = new_command.clone();
+                    state.navigator.back();
+                }
+                Some(DismissNotice) => state.notice = None,
+                Some(DismissOutput) => state.output = None,
+                Some(ScrollOutput(distance)) => ui.scroll_widget(ids.list_canvas, [0.0, distance]),
+                Some(EditNoticeFile) => {
+                    let notice = state.notice.as_ref();
+                    if let Some(file) = notice.and_then(|notice| notice.file.clone()) {
+                        let line = notice.and_then(|notice| notice.location.as_ref()).map(|location| location.line);
+                        match editor::open_in_window_editor(&file, line) {
+                            Ok(_) => close = true,
+                            Err(err) => {
+                                state.notice = Some(Notice::error(format!(
+                                    "Could not open {}: {}",
+                                    file.display(),
+                                    err
+                                )))
+                            }
                         }
                     }
                 }
@@ -120,10 +286,55 @@ fn main() {
                         .set_cursor_position((0, 0).into())
                         .unwrap();
                 }
-                Some(CloseApplication) => break 'main,
+                Some(CloseApplication) => close = true,
                 None => (),
             }
         }
+        // Keys like F1 type no character to wait for
+        if matches!(&pressed_key, Some(key) if !key.types_character()) {
+            let key = pressed_key.take().expect("a key is waiting");
+            close |= press_key(&mut state, &KeyPress::from(key), &output_proxy, &mut reopener);
+        }
+        if let Some(output) = &mut state.output {
+            if output.update() {
+                event_loop.needs_update();
+            }
+        }
+        if close {
+            if !is_daemon && !reopener.is_waiting() {
+                break 'main;
+            }
+            // The daemon keeps running, ready to show the window again.
+            let _ = daemon::handle_call(Call::Hide, &mut state, &display);
+            pressed_key = None;
+        }
+        for finished in reopener.finished() {
+            let path = finished.path.clone();
+            state.finished = Some(finished);
+            if let Err(err) = daemon::handle_call(Call::Show(path), &mut state, &display) {
+                state.notice = Some(Notice::error(err.message));
+                let _ = daemon::handle_call(Call::Show(vec![]), &mut state, &display);
+            }
+            event_loop.needs_update();
+        }
+        if let Some(requests) = &daemon_requests {
+            for request in requests.try_iter() {
+                let result = daemon::handle_call(request.call, &mut state, &display);
+                let _ = request.reply.send(result);
+                if let Some(config_watcher) = &mut config_watcher {
+                    config_watcher.watch(watched_config_files(&state));
+                }
+                event_loop.needs_update();
+            }
+        }
+        if let Some(config_watcher) = &mut config_watcher {
+            if config_watcher.files_changed() {
+                eprintln!("Config changed, reloading");
+                let _ = state.reload_config(config::load_config(&state.options));
+                config_watcher.watch(watched_config_files(&state));
+                event_loop.needs_update();
+            }
+        }
         render(
             &mut state,
             &mut ui,
@@ -135,6 +346,56 @@ fn main() {
     }
 }
 
+/**
+ * Press `key_press` in the window, returns whether to close it.
+ *
+ * The window stays open after running a command from a sticky menu.
+ * A command showing its output keeps the window open, `output_proxy` wakes
+ * it up as the output comes in. A command with a `then` is waited for by
+ * `reopener`.
+ */
+fn press_key(
+    state: &mut State,
+    key_press: &KeyPress,
+    output_proxy: &EventsLoopProxy,
+    reopener: &mut Reopener,
+) -> bool {
+    match state.navigator.press_key(key_press) {
+        Outcome::Run(leaf) if leaf.output.unwrap_or_default() == Output::Show => {
+            match CommandOutput::start(&leaf, &state.last_run, output_proxy.clone()) {
+                Ok(output) => state.output = Some(output),
+                Err(err) => state.notice = Some(Notice::error(err)),
+            }
+            false
+        }
+        Outcome::Run(leaf) => {
+            let started = match &leaf.then {
+                Some(then) => {
+                    let path = then.path(&state.navigator);
+                    reopener.start(&leaf, path, &state.last_run)
+                }
+                None => exec::run_in_background(&leaf, &state.last_run),
+            };
+            match started {
+                Ok(()) => !state.navigator.stays_open(&leaf),
+                Err(err) => {
+                    state.notice = Some(Notice::error(err));
+                    false
+                }
+            }
+        }
+        Outcome::Entered | Outcome::Pending | Outcome::StickyToggled | Outcome::Unbound => false,
+    }
+}
+
+/// The files whose changes reload the config.
+fn watched_config_files(state: &State) -> Vec<PathBuf> {
+    let mut files = config::config_file_candidates(&state.options);
+    files.extend(state.config.sources.iter().cloned());
+    files.extend(state.notice.as_ref().and_then(|notice| notice.file.clone()));
+    files
+}
+
 fn render(
     state: &mut State,
     ui: &mut conrod::Ui,
@@ -143,18 +404,18 @@ fn render(
     display: &glium::Display,
     image_map: &conrod::image::Map<glium::texture::Texture2d>,
 ) {
-    set_ui(ui.set_widgets(), &state, &state.selected_command, ids);
+    set_ui(ui.set_widgets(), state, ids);
 
     // Render the `Ui` and then display it on the screen.
     if let Some(primitives) = ui.draw_if_changed() {
-        if let Some(height) = rendered_elements_height(ui, ids) {
+        if let Some(height) = rendered_elements_height(ui, ids, state) {
             let new_window_height = height;
-            update_window_and_window_state(new_window_height, state, &display, false);
+            update_window_and_window_state(new_window_height, state, display, false);
         }
-        renderer.fill(&display, primitives, &image_map);
+        renderer.fill(display, primitives, image_map);
         let mut target = display.draw();
         target.clear_color(0.0, 0.0, 0.0, 1.0);
-        renderer.draw(display, &mut target, &image_map).unwrap();
+        renderer.draw(display, &mut target, image_map).unwrap();
         target.finish().unwrap();
     }
 }
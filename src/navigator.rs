@@ -0,0 +1,448 @@
+use crate::bindings::{KeyPress, Matching, MenuCmdKey, Shortcut, ShortcutPath};
+use crate::commands::{Command, CommandDisplay, CommandLeaf};
+use crate::config::SpacerunConfig;
+
+static DEFAULT_STICKY_KEY: &str = "C-s";
+
+/**
+ * Walks down the command tree as shortcuts are pressed.
+ *
+ * Frontends turn their key events into `Shortcut`s and run the commands
+ * `press` selects, everything in between happens here.
+ */
+#[derive(Debug, Clone)]
+pub struct Navigator {
+    root: Command,
+    /// The menus opened on the way from the root to the current one
+    path: Vec<Command>,
+    /// The keys pressed of a key sequence that is not finished yet
+    pending: Vec<Shortcut>,
+    menu_cmd_key: MenuCmdKey,
+    sticky_key: Shortcut,
+    /// Set by the sticky key, overriding whether the menus are sticky
+    sticky: Option<bool>,
+}
+
+/**
+ * What pressing a shortcut did
+ */
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    /// A menu was opened, it is the current one now
+    Entered,
+    /// The keys pressed so far start a key sequence, more have to follow
+    Pending,
+    /// A command was selected, the frontend should run it
+    Run(Box<CommandLeaf>),
+    /// The sticky key was pressed, see `is_sticky`
+    StickyToggled,
+    /// Nothing is bound to the shortcut in the current menu
+    Unbound,
+}
+
+impl Navigator {
+    pub fn new(config: &SpacerunConfig) -> Navigator {
+        Navigator {
+            root: config.commands.clone(),
+            path: vec![],
+            pending: vec![],
+            menu_cmd_key: config.menu_cmd_key.clone().unwrap_or_default(),
+            sticky_key: sticky_key(config),
+            sticky: None,
+        }
+    }
+
+    /// Press exactly `shortcut` in the current menu.
+    pub fn press(&mut self, shortcut: &Shortcut) -> Outcome {
+        self.advance(|bound, _| bound == shortcut)
+    }
+
+    /// Press a key in the current menu, matched as each shortcut says.
+    pub fn press_key(&mut self, key_press: &KeyPress) -> Outcome {
+        self.advance(|bound, matching| key_press.matches(bound, matching))
+    }
+
+    /**
+     * Continue the pending key sequence with the key `matches` accepts.
+     *
+     * A child whose key sequence is finished by the key is selected, even
+     * if the sequences of other children continue after it.
+     */
+    fn advance<F: Fn(&Shortcut, Matching) -> bool>(&mut self, matches: F) -> Outcome {
+        let depth = self.pending.len();
+        let (finished, continued) = {
+            let mut candidates = self.current().children().iter().filter(|child| {
+                let keys = &child.shortcut().0;
+                keys.len() > depth
+                    && keys.starts_with(&self.pending)
+                    && matches(&keys[depth], child.shortcut_matching())
+            });
+            let finished = candidates
+                .clone()
+                .find(|child| child.shortcut().0.len() == depth + 1)
+                .cloned();
+            let continued = candidates.next().map(|child| child.shortcut().0[depth].clone());
+            (finished, continued)
+        };
+
+        match (finished, continued) {
+            (Some(child), _) => {
+                self.pending.clear();
+                match child {
+                    node @ Command::Node(_) => {
+                        self.path.push(node);
+                        Outcome::Entered
+                    }
+                    Command::Leaf(leaf) => Outcome::Run(Box::new(leaf)),
+                }
+            }
+            (None, Some(key)) => {
+                self.pending.push(key);
+                Outcome::Pending
+            }
+            (None, None) if !self.pending.is_empty() => {
+                self.pending.clear();
+                Outcome::Unbound
+            }
+            (None, None) => {
+                let matching = self.current().shortcut_matching();
+                match self.menu_cmd_shortcut() {
+                    Some(shortcut) if matches(&shortcut.0[0], matching) => {
+                        Outcome::Run(Box::new(self.menu_cmd().expect("the menu has a cmd")))
+                    }
+                    _ if matches(&self.sticky_key, matching) => {
+                        self.sticky = Some(!self.is_sticky());
+                        Outcome::StickyToggled
+                    }
+                    _ => Outcome::Unbound,
+                }
+            }
+        }
+    }
+
+    /// The current menu as a command to run, if it has a `cmd`.
+    fn menu_cmd(&self) -> Option<CommandLeaf> {
+        match self.current() {
+            Command::Node(node) => node.cmd_leaf(),
+            Command::Leaf(_) => None,
+        }
+    }
+
+    /**
+     * The key running the `cmd` of the current menu, if it has one.
+     *
+     * Keys bound to children of the menu select those instead.
+     */
+    fn menu_cmd_shortcut(&self) -> Option<ShortcutPath> {
+        self.menu_cmd()?;
+        let shortcut = match &self.menu_cmd_key {
+            MenuCmdKey::Shortcut(shortcut) => shortcut.clone(),
+            MenuCmdKey::Repeat => self.current().shortcut().0.last()?.clone(),
+        };
+        Some(ShortcutPath(vec![shortcut]))
+    }
+
+    /**
+     * Whether the window stays open after running a command of the current
+     * menu.
+     *
+     * The sticky key decides once pressed, otherwise the closest menu on the
+     * way to the current one that says so.
+     */
+    pub fn is_sticky(&self) -> bool {
+        let menus = std::iter::once(&self.root).chain(&self.path);
+        self.sticky
+            .or_else(|| menus.rev().find_map(Command::sticky))
+            .unwrap_or(false)
+    }
+
+    /// Whether the window stays open after running `leaf` from the current menu.
+    pub fn stays_open(&self, leaf: &CommandLeaf) -> bool {
+        leaf.sticky.unwrap_or_else(|| self.is_sticky())
+    }
+
+    /**
+     * The entries the current menu shows.
+     *
+     * The menu's own `cmd` comes first, bound to the key running it.
+     */
+    pub fn entries(&self) -> Vec<CommandDisplay> {
+        let mut entries = vec![];
+        if let Some(shortcut) = self.menu_cmd_shortcut() {
+            entries.push(CommandDisplay {
+                shortcut,
+                name: self.current().name().to_owned(),
+            });
+        }
+        entries.extend(self.current().displayable_children());
+        entries
+    }
+
+    /**
+     * Take back the last key of a pending key sequence, or go back to the
+     * parent menu.
+     *
+     * Returns whether there was anything to go back from.
+     */
+    pub fn back(&mut self) -> bool {
+        self.pending.pop().is_some() || self.path.pop().is_some()
+    }
+
+    /// Go back to the root menu, forgetting the sticky key was pressed.
+    pub fn reset(&mut self) {
+        self.path.clear();
+        self.pending.clear();
+        self.sticky = None;
+    }
+
+    /// The current menu.
+    pub fn current(&self) -> &Command {
+        self.path.last().unwrap_or(&self.root)
+    }
+
+    /// The menus opened on the way to the current one, without the root.
+    pub fn path(&self) -> &[Command] {
+        &self.path
+    }
+
+    /// The shortcuts leading from the root to the current menu.
+    pub fn shortcuts(&self) -> Vec<Shortcut> {
+        self.path
+            .iter()
+            .flat_map(|menu| menu.shortcut().0.clone())
+            .collect()
+    }
+
+    /// The keys pressed of a key sequence that is not finished yet.
+    pub fn pending(&self) -> &[Shortcut] {
+        &self.pending
+    }
+
+    /**
+     * The names of the menus on the way to the current one, e.g. `Root > Git`.
+     *
+     * The keys of a pending key sequence follow, e.g. `Root > Git > g-`.
+     */
+    pub fn breadcrumbs(&self) -> String {
+        let breadcrumbs = self.path.iter().fold("Root".into(), |acc, menu| {
+            format!("{} > {}", acc, menu.name())
+        });
+        if self.pending.is_empty() {
+            breadcrumbs
+        } else {
+            format!("{} > {}-", breadcrumbs, ShortcutPath(self.pending.clone()))
+        }
+    }
+
+    /**
+     * Open the menu reached by `path` from the root.
+     *
+     * If there is none, the current menu stays as it is.
+     */
+    pub fn open(&mut self, path: &[Shortcut]) -> Result<(), String> {
+        let menus = resolve_menu(&self.root, path)?;
+        self.path = menus.into_iter().cloned().collect();
+        self.pending.clear();
+        Ok(())
+    }
+
+    /**
+     * Walk the command tree of the reloaded `config` instead, staying in the
+     * current menu if it still exists.
+     *
+     * The path is cut where it no longer leads to a menu, e.g. as a reload
+     * of the config removed it.
+     */
+    pub fn set_config(&mut self, config: &SpacerunConfig) {
+        let shortcuts = self.shortcuts();
+        let sticky = self.sticky;
+        self.root = config.commands.clone();
+        self.menu_cmd_key = config.menu_cmd_key.clone().unwrap_or_default();
+        self.sticky_key = sticky_key(config);
+        self.reset();
+        for shortcut in &shortcuts {
+            match self.press(shortcut) {
+                Outcome::Entered | Outcome::Pending => {}
+                Outcome::Run(_) | Outcome::StickyToggled | Outcome::Unbound => break,
+            }
+        }
+        // The sequence of the last menu was cut short
+        self.pending.clear();
+        self.sticky = sticky;
+    }
+}
+
+/// The key toggling sticky mode in `config`.
+fn sticky_key(config: &SpacerunConfig) -> Shortcut {
+    config.sticky_key.clone().unwrap_or_else(|| {
+        DEFAULT_STICKY_KEY
+            .parse()
+            .expect("the default sticky key is a valid shortcut")
+    })
+}
+
+/**
+ * Resolve the menus on the way to the menu `path` leads to from `root`.
+ *
+ * Fails if a shortcut of the path does not exist, or if the path ends in a
+ * command instead of a menu.
+ */
+pub fn resolve_menu<'a>(root: &'a Command, path: &[Shortcut]) -> Result<Vec<&'a Command>, String> {
+    let commands = root.resolve_path(path).map_err(|err| err.to_string())?;
+    if let Some(Command::Leaf(leaf)) = commands.last() {
+        return Err(format!(
+            "`{}` is a command, not a menu. Use `spacerun exec` to run it.",
+            leaf.name
+        ));
+    }
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn config(commands: Value) -> SpacerunConfig {
+        serde_json::from_value(json!({ "commands": commands })).unwrap()
+    }
+
+    fn shortcut(shortcut: &str) -> Shortcut {
+        shortcut.parse().unwrap()
+    }
+
+    /// Root with the menu `a` (`b`, `c d` and the menu `m`) and the command `x`.
+    fn example_config() -> SpacerunConfig {
+        config(json!({
+            "shortcut": "SPC",
+            "name": "Root",
+            "children": [
+                {
+                    "shortcut": "a",
+                    "name": "A",
+                    "children": [
+                        {"shortcut": "b", "name": "B", "cmd": "b"},
+                        {"shortcut": "c d", "name": "CD", "cmd": "cd"},
+                        {
+                            "shortcut": "m",
+                            "name": "M",
+                            "children": [{"shortcut": "n", "name": "N", "cmd": "n"}],
+                        },
+                    ],
+                },
+                {"shortcut": "x", "name": "X", "cmd": "x"},
+            ],
+        }))
+    }
+
+    fn names(navigator: &Navigator) -> Vec<&str> {
+        navigator.path().iter().map(Command::name).collect()
+    }
+
+    #[test]
+    fn enter_run_and_unbound() {
+        let mut navigator = Navigator::new(&example_config());
+        assert!(matches!(navigator.press(&shortcut("z")), Outcome::Unbound));
+        assert!(matches!(navigator.press(&shortcut("a")), Outcome::Entered));
+        assert_eq!(navigator.current().name(), "A");
+        match navigator.press(&shortcut("b")) {
+            Outcome::Run(leaf) => assert_eq!(leaf.name, "B"),
+            outcome => panic!("expected to run B, got {:?}", outcome),
+        }
+        assert!(matches!(navigator.press(&shortcut("c")), Outcome::Pending));
+        assert_eq!(navigator.pending(), &[shortcut("c")]);
+        match navigator.press(&shortcut("d")) {
+            Outcome::Run(leaf) => assert_eq!(leaf.name, "CD"),
+            outcome => panic!("expected to run CD, got {:?}", outcome),
+        }
+        assert!(navigator.pending().is_empty());
+        assert!(matches!(navigator.press(&shortcut("c")), Outcome::Pending));
+        assert!(matches!(navigator.press(&shortcut("z")), Outcome::Unbound));
+        assert!(navigator.pending().is_empty());
+        assert!(matches!(navigator.press(&shortcut("x")), Outcome::Unbound));
+        assert_eq!(navigator.current().name(), "A");
+        assert_eq!(navigator.breadcrumbs(), "Root > A");
+    }
+
+    #[test]
+    fn back_takes_back_pending_keys_before_menus() {
+        let mut navigator = Navigator::new(&example_config());
+        navigator.press(&shortcut("a"));
+        navigator.press(&shortcut("m"));
+        assert!(navigator.back());
+        assert_eq!(navigator.current().name(), "A");
+        assert!(navigator.back());
+        assert_eq!(navigator.current().name(), "Root");
+        assert!(!navigator.back());
+
+        navigator.press(&shortcut("a"));
+        navigator.press(&shortcut("c"));
+        assert!(navigator.back());
+        assert!(navigator.pending().is_empty());
+        assert_eq!(navigator.current().name(), "A");
+
+        navigator.open(&[shortcut("a"), shortcut("m")]).unwrap();
+        assert_eq!(names(&navigator), ["A", "M"]);
+        navigator.reset();
+        assert!(navigator.path().is_empty());
+    }
+
+    #[test]
+    fn reset_forgets_the_sticky_key() {
+        let mut navigator = Navigator::new(&example_config());
+        navigator.press(&shortcut("a"));
+        assert!(!navigator.is_sticky());
+        assert!(matches!(navigator.press(&shortcut("C-s")), Outcome::StickyToggled));
+        assert!(navigator.is_sticky());
+        navigator.reset();
+        assert!(!navigator.is_sticky());
+        assert!(navigator.path().is_empty());
+    }
+
+    #[test]
+    fn set_config_stays_in_a_menu_that_still_exists() {
+        let mut navigator = Navigator::new(&example_config());
+        navigator.press(&shortcut("a"));
+        navigator.press(&shortcut("m"));
+        navigator.press(&shortcut("C-s"));
+        let mut reloaded = example_config();
+        if let Command::Node(root) = &mut reloaded.commands {
+            if let Command::Node(a) = &mut root.children[0] {
+                a.name = "Renamed".into();
+            }
+        }
+        navigator.set_config(&reloaded);
+        assert_eq!(names(&navigator), ["Renamed", "M"]);
+        assert!(navigator.is_sticky());
+    }
+
+    #[test]
+    fn set_config_cuts_the_path_where_a_menu_was_removed() {
+        let mut navigator = Navigator::new(&example_config());
+        navigator.press(&shortcut("a"));
+        navigator.press(&shortcut("m"));
+        let reloaded = config(json!({
+            "shortcut": "SPC",
+            "name": "Root",
+            "children": [
+                {
+                    "shortcut": "a",
+                    "name": "A",
+                    "children": [{"shortcut": "m", "name": "M", "cmd": "m"}],
+                },
+            ],
+        }));
+        navigator.set_config(&reloaded);
+        assert_eq!(names(&navigator), ["A"]);
+
+        let reloaded = config(json!({
+            "shortcut": "SPC",
+            "name": "Root",
+            "children": [{"shortcut": "x", "name": "X", "cmd": "x"}],
+        }));
+        navigator.set_config(&reloaded);
+        assert!(navigator.path().is_empty());
+        assert!(navigator.pending().is_empty());
+    }
+}
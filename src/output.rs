@@ -0,0 +1,150 @@
+use std::io::{prelude::*, BufReader};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use conrod::backend::glium::glium::glutin::EventsLoopProxy;
+use serde_derive::Deserialize;
+
+use crate::action::Streams;
+use crate::commands::CommandLeaf;
+use crate::exec::{self, LastRun, RunStatus};
+
+/// Bytes of output kept, older lines are dropped beyond it.
+const MAX_OUTPUT_LEN: usize = 100_000;
+
+/**
+ * What happens to the output of a command run from the window
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+    /// The command writes to spacerun's own stdout and the window closes
+    #[default]
+    Detach,
+    /// The window stays open and shows the output as it comes in
+    Show,
+}
+
+enum OutputEvent {
+    Line(String),
+    Finished(RunStatus),
+}
+
+/**
+ * The output of a command started from the window, shown instead of the
+ * commands while set
+ */
+pub struct CommandOutput {
+    pub name: String,
+    /// stdout and stderr, in the order their lines were read
+    pub text: String,
+    pub status: RunStatus,
+    events: Receiver<OutputEvent>,
+}
+
+impl CommandOutput {
+    /**
+     * Start `leaf`, capturing its output.
+     *
+     * Every line read and the end of the command wake up the events loop of
+     * `proxy`, `update` takes them in.
+     */
+    pub fn start(
+        leaf: &CommandLeaf,
+        last_run: &LastRun,
+        proxy: EventsLoopProxy,
+    ) -> Result<CommandOutput, String> {
+        let mut child = exec::start(leaf, Streams::Captured, last_run)?;
+        let (sender, events) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            read_lines(stdout, sender.clone(), proxy.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            read_lines(stderr, sender.clone(), proxy.clone());
+        }
+        // Processes the command left running in the background may keep its
+        // output open, so the readers are not waited for. Lines still on
+        // their way are appended after the status.
+        exec::wait_in_background(child, last_run, move |status| {
+            let _ = sender.send(OutputEvent::Finished(status));
+            let _ = proxy.wakeup();
+        });
+
+        Ok(CommandOutput {
+            name: leaf.name.clone(),
+            text: String::new(),
+            status: RunStatus::Running,
+            events,
+        })
+    }
+
+    /// Take in what happened since the last call, returns whether anything did.
+    pub fn update(&mut self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter() {
+            match event {
+                OutputEvent::Line(line) => self.text.push_str(&line),
+                OutputEvent::Finished(status) => self.status = status,
+            }
+            changed = true;
+        }
+
+        if self.text.len() > MAX_OUTPUT_LEN {
+            let cut = self.text.len() - MAX_OUTPUT_LEN;
+            let start = match self.text.as_bytes()[cut..].iter().position(|&byte| byte == b'\n') {
+                Some(newline) => cut + newline + 1,
+                None => (cut..)
+                    .find(|&index| self.text.is_char_boundary(index))
+                    .expect("the end of the text is a char boundary"),
+            };
+            self.text.drain(..start);
+        }
+        changed
+    }
+
+    /// The name of the command and whether it is still running.
+    pub fn title(&self) -> String {
+        format!("{} ({})", self.name, self.status)
+    }
+
+    /// The output, followed by the keys to leave it.
+    pub fn full_text(&self) -> String {
+        let mut text = match &self.status {
+            RunStatus::Running => self.text.clone(),
+            _ if self.text.is_empty() => "No output".into(),
+            _ => self.text.clone(),
+        };
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str("\n[Backspace] Back   [Esc] Close");
+        text
+    }
+}
+
+/**
+ * Send each line of `stream` as it is read.
+ *
+ * Reading goes on after the output was closed, so the command does not get
+ * stuck on a full pipe.
+ */
+fn read_lines<R>(stream: R, sender: Sender<OutputEvent>, proxy: EventsLoopProxy)
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut line = vec![];
+        loop {
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            let text = String::from_utf8_lossy(&line).into_owned();
+            if sender.send(OutputEvent::Line(text)).is_ok() {
+                let _ = proxy.wakeup();
+            }
+            line.clear();
+        }
+    });
+}
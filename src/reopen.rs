@@ -0,0 +1,118 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use conrod::backend::glium::glium::glutin::EventsLoopProxy;
+use serde::de::{self, Deserialize, Deserializer};
+
+use crate::action::Streams;
+use crate::bindings::{Shortcut, ShortcutPath};
+use crate::commands::CommandLeaf;
+use crate::exec::{self, LastRun, RunStatus};
+use crate::navigator::Navigator;
+
+/**
+ * What spacerun does once a command finished
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Then {
+    /// Show the menu the command was run from again
+    Reopen,
+    /// Show the menu reached by the shortcuts from the root
+    Path(ShortcutPath),
+}
+
+impl<'de> Deserialize<'de> for Then {
+    fn deserialize<D>(deserializer: D) -> Result<Then, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let then = String::deserialize(deserializer)?;
+        let then = then.trim();
+        if then == "reopen" {
+            return Ok(Then::Reopen);
+        }
+        match then.strip_prefix("path") {
+            Some(path) if path.trim().is_empty() => Err(de::Error::custom(
+                "`path` needs the shortcuts of a menu, e.g. `path w n`",
+            )),
+            Some(path) if path.starts_with(char::is_whitespace) => {
+                path.parse().map(Then::Path).map_err(de::Error::custom)
+            }
+            _ => Err(de::Error::custom(format!(
+                "Unknown `then` `{}`, expected `reopen` or `path` with shortcuts, e.g. `path w n`",
+                then
+            ))),
+        }
+    }
+}
+
+impl Then {
+    /// The shortcuts of the menu to show, `navigator` being where the command was run.
+    pub fn path(&self, navigator: &Navigator) -> Vec<Shortcut> {
+        match self {
+            Then::Reopen => navigator.shortcuts(),
+            Then::Path(path) => path.0.clone(),
+        }
+    }
+}
+
+/**
+ * A command with a `then` that finished
+ */
+#[derive(Debug, Clone)]
+pub struct Finished {
+    pub name: String,
+    /// The menu to show
+    pub path: Vec<Shortcut>,
+    pub status: RunStatus,
+}
+
+/**
+ * Waits for commands with a `then`, to show the window again once they
+ * finished
+ */
+pub struct Reopener {
+    sender: Sender<Finished>,
+    finished: Receiver<Finished>,
+    proxy: EventsLoopProxy,
+    /// Commands started that did not finish yet
+    running: usize,
+}
+
+impl Reopener {
+    /// Create a reopener waking up the events loop of `proxy` as commands finish.
+    pub fn new(proxy: EventsLoopProxy) -> Reopener {
+        let (sender, finished) = mpsc::channel();
+        Reopener {
+            sender,
+            finished,
+            proxy,
+            running: 0,
+        }
+    }
+
+    /// Start `leaf`, the menu reached by `path` is shown once it finished.
+    pub fn start(&mut self, leaf: &CommandLeaf, path: Vec<Shortcut>, last_run: &LastRun) -> Result<(), String> {
+        let child = exec::start(leaf, Streams::Inherited, last_run)?;
+        let sender = self.sender.clone();
+        let proxy = self.proxy.clone();
+        let name = leaf.name.clone();
+        exec::wait_in_background(child, last_run, move |status| {
+            let _ = sender.send(Finished { name, path, status });
+            let _ = proxy.wakeup();
+        });
+        self.running += 1;
+        Ok(())
+    }
+
+    /// Whether a command was started that did not finish yet.
+    pub fn is_waiting(&self) -> bool {
+        self.running > 0
+    }
+
+    /// The commands that finished since the last call.
+    pub fn finished(&mut self) -> Vec<Finished> {
+        let finished: Vec<Finished> = self.finished.try_iter().collect();
+        self.running -= finished.len();
+        finished
+    }
+}
@@ -1,7 +1,14 @@
+use std::path::PathBuf;
+
 use conrod::glium::glutin::dpi::{LogicalPosition, LogicalSize};
 
-use crate::commands::{Command, CommandDisplay};
-use crate::config::SpacerunConfig;
+use crate::bindings::Shortcut;
+use crate::config::{ConfigError, SpacerunConfig};
+use crate::config_format::Location;
+use crate::exec::LastRun;
+use crate::navigator::Navigator;
+use crate::output::CommandOutput;
+use crate::reopen::Finished;
 use crate::Options;
 
 const DEFAULT_WINDOW_WIDTH: f64 = 500.0;
@@ -11,33 +18,172 @@ pub struct State {
     pub window_position: LogicalPosition,
     pub window_dimensions: LogicalSize,
     pub config: SpacerunConfig,
-    pub selected_command: Command,
-    pub selection_path: Vec<CommandDisplay>,
+    pub navigator: Navigator,
     pub options: Options,
+    /// Shown instead of the commands while set
+    pub notice: Option<Notice>,
+    /// Output of a command started from the window, shown instead of the
+    /// commands while set
+    pub output: Option<CommandOutput>,
+    /// The command with a `then` that showed the window again
+    pub finished: Option<Finished>,
+    /// Whether the window is shown, only the daemon hides it
+    pub window_visible: bool,
+    pub last_run: LastRun,
+}
+
+/**
+ * A message shown in the window instead of the commands, e.g. an error
+ */
+#[derive(Debug, Clone)]
+pub struct Notice {
+    pub title: String,
+    pub text: String,
+    /// File the message is about, which can be opened in an editor
+    pub file: Option<PathBuf>,
+    pub location: Option<Location>,
+}
+
+impl Notice {
+    pub fn error<S: Into<String>>(text: S) -> Notice {
+        Notice {
+            title: "Error".into(),
+            text: text.into(),
+            file: None,
+            location: None,
+        }
+    }
+
+    /// Greets the user after `config::write_starter_config` wrote `file`.
+    pub fn starter_config(file: PathBuf) -> Notice {
+        Notice {
+            title: "Welcome to spacerun".into(),
+            text: "No config was found, so a starter config was created. \
+                   Edit it to add your own commands."
+                .into(),
+            file: Some(file),
+            location: None,
+        }
+    }
+
+    /// The text, followed by the file and the keys to handle the notice.
+    pub fn full_text(&self) -> String {
+        let mut text = self.text.clone();
+        if let Some(file) = &self.file {
+            text.push_str(&format!("\n\nFile: {}", file.display()));
+            if let Some(location) = self.location {
+                text.push_str(&format!(", line {}, column {}", location.line, location.column));
+            }
+            text.push_str("\n\n[e] Edit the file   [Backspace] Back   [Esc] Close");
+        } else {
+            text.push_str("\n\n[Backspace] Back   [Esc] Close");
+        }
+        text
+    }
+
+    pub fn config_error(err: &ConfigError) -> Notice {
+        Notice {
+            title: "Error loading the config".into(),
+            text: err.to_string(),
+            file: err.file().map(ToOwned::to_owned),
+            location: err.location(),
+        }
+    }
 }
 
 impl State {
-    pub fn new(config: SpacerunConfig, options: Options) -> State {
+    pub fn new(config: SpacerunConfig, options: Options) -> Result<State, String> {
+        let mut navigator = Navigator::new(&config);
+        navigator.open(&initial_path(&options))?;
         let state = State {
             window_dimensions: (DEFAULT_WINDOW_WIDTH, DEFAULT_WINDOW_HEIGHT).into(),
             window_position: (0, 0).into(),
-            selected_command: select_initial_command(&config, &options),
-            selection_path: vec![],
+            navigator,
             config,
             options,
+            notice: None,
+            output: None,
+            finished: None,
+            window_visible: false,
+            last_run: LastRun::default(),
         };
-        return state;
+        Ok(state)
     }
-}
 
-fn select_initial_command(config: &SpacerunConfig, options: &Options) -> Command {
-    let mut command = &config.commands;
+    /**
+     * Go back to the root menu, e.g. as the window was hidden.
+     *
+     * Notices about a file are kept, as the file still has to be fixed.
+     */
+    pub fn reset(&mut self) {
+        self.navigator.reset();
+        self.output = None;
+        self.finished = None;
+        if let Some(Notice { file: None, .. }) = self.notice {
+            self.notice = None;
+        }
+    }
+
+    /**
+     * The breadcrumbs of the current menu, followed by whether it is sticky
+     * and how the command that showed the window again ended.
+     */
+    pub fn breadcrumbs(&self) -> String {
+        let mut breadcrumbs = self.navigator.breadcrumbs();
+        if self.navigator.is_sticky() {
+            breadcrumbs.push_str("   [sticky]");
+        }
+        match &self.finished {
+            Some(finished) => format!("{}   [{}: {}]", breadcrumbs, finished.name, finished.status),
+            None => breadcrumbs,
+        }
+    }
+
+    /**
+     * Use the reloaded `config`, staying in the current menu if it still
+     * exists.
+     *
+     * If the config could not be loaded, the old one is kept and the error
+     * is shown and returned.
+     */
+    pub fn reload_config(&mut self, config: Result<SpacerunConfig, ConfigError>) -> Result<(), String> {
+        match config {
+            Ok(config) => {
+                self.navigator.set_config(&config);
+                self.config = config;
+                self.notice = None;
+                Ok(())
+            }
+            Err(err) => {
+                self.notice = Some(Notice::config_error(&err));
+                Err(err.to_string())
+            }
+        }
+    }
 
-    if let Some(shortcut) = &options.initial_shortcut {
-        if let Some(child) = command.find_child_for_shortcut(shortcut) {
-            command = child;
+    /// State for a window only showing `notice`, e.g. as there is no config.
+    pub fn with_notice(options: Options, notice: Notice) -> State {
+        let config = SpacerunConfig::empty();
+        State {
+            window_dimensions: (DEFAULT_WINDOW_WIDTH, DEFAULT_WINDOW_HEIGHT).into(),
+            window_position: (0, 0).into(),
+            navigator: Navigator::new(&config),
+            config,
+            options,
+            notice: Some(notice),
+            output: None,
+            finished: None,
+            window_visible: false,
+            last_run: LastRun::default(),
         }
-    };
+    }
+}
 
-    command.to_owned()
+/// The shortcuts leading to the menu spacerun should start in.
+pub fn initial_path(options: &Options) -> Vec<Shortcut> {
+    match (&options.initial_path, &options.initial_shortcut) {
+        (Some(path), _) => path.0.clone(),
+        (None, Some(shortcut)) => vec![shortcut.clone()],
+        (None, None) => vec![],
+    }
 }
@@ -0,0 +1,204 @@
+use std::io::{self, prelude::*, StdinLock};
+use std::path::Path;
+
+use termion::event::Key;
+use termion::input::{Keys, TermRead};
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::screen::{self, AlternateScreen};
+use termion::{clear, cursor, style};
+
+use crate::action::Streams;
+use crate::bindings::{KeyCode, KeyPress, Modifiers, Shortcut};
+use crate::commands::CommandLeaf;
+use crate::config;
+use crate::editor;
+use crate::exec::RunStatus;
+use crate::navigator::Outcome;
+use crate::reopen::Finished;
+use crate::state::{Notice, State};
+
+type Screen = AlternateScreen<RawTerminal<io::Stdout>>;
+
+/**
+ * Show the menus in the terminal instead of a window.
+ *
+ * A selected command runs in the terminal once the menu is closed, a command
+ * with a `then` or run from a sticky menu shows the menu again once it
+ * finished. Returns the exit code
+ * spacerun should exit with, which is the one of the last command if one was
+ * run.
+ */
+pub fn run(mut state: State) -> i32 {
+    // Shared by every menu shown, as it may have read ahead keys typed while
+    // a command ran
+    let stdin = io::stdin();
+    let mut keys = stdin.lock().keys();
+    let mut exit_code = 0;
+    loop {
+        let picked = navigate(&mut state, &mut keys);
+        // Leaving the alternate screen is buffered until the next newline.
+        let _ = io::stdout().flush();
+        let leaf = match picked {
+            Ok(Some(leaf)) => leaf,
+            Ok(None) => return exit_code,
+            Err(err) => {
+                eprintln!("Terminal error: {}", err);
+                return 1;
+            }
+        };
+
+        let status = match leaf.spawn(Streams::Inherited).and_then(|mut child| child.wait()) {
+            Ok(status) => RunStatus::Exited(status.code()),
+            Err(err) => {
+                eprintln!("Could not run `{}`: {}", leaf.action, err);
+                RunStatus::Failed(err.to_string())
+            }
+        };
+        exit_code = match status {
+            RunStatus::Exited(Some(code)) => code,
+            _ => 1,
+        };
+
+        let then = match &leaf.then {
+            Some(then) => then,
+            // Back to the menu the command was run from
+            None if state.navigator.stays_open(&leaf) => continue,
+            None => return exit_code,
+        };
+        let path = then.path(&state.navigator);
+        if let Err(err) = state.navigator.open(&path) {
+            state.notice = Some(Notice::error(err));
+        }
+        state.finished = Some(Finished {
+            name: leaf.name.clone(),
+            path,
+            status,
+        });
+    }
+}
+
+/// Let the user pick a command, `None` if the menu was closed.
+fn navigate(state: &mut State, keys: &mut Keys<StdinLock>) -> io::Result<Option<CommandLeaf>> {
+    let mut screen = AlternateScreen::from(io::stdout().into_raw_mode()?);
+    write!(screen, "{}", cursor::Hide)?;
+    state.window_visible = true;
+
+    let picked = loop {
+        draw(&mut screen, state)?;
+        let key = match keys.next() {
+            Some(key) => key?,
+            None => break None,
+        };
+
+        if let Some(notice) = &state.notice {
+            match key {
+                Key::Char('e') if notice.file.is_some() => {
+                    let file = notice.file.clone().unwrap();
+                    let line = notice.location.as_ref().map(|location| location.line);
+                    edit(&mut screen, state, &file, line)?;
+                }
+                Key::Backspace => state.notice = None,
+                Key::Esc | Key::Ctrl('c') => break None,
+                _ => {}
+            }
+            continue;
+        }
+
+        let outcome = state.navigator.press_key(&key_press_for_key(key));
+        match (outcome, key) {
+            (Outcome::Entered, _) | (Outcome::Pending, _) | (Outcome::StickyToggled, _) => {}
+            (Outcome::Run(leaf), _) => break Some(*leaf),
+            (Outcome::Unbound, Key::Backspace) => {
+                state.navigator.back();
+            }
+            (Outcome::Unbound, Key::Esc) | (Outcome::Unbound, Key::Ctrl('c')) => break None,
+            (Outcome::Unbound, _) => {}
+        }
+    };
+
+    write!(screen, "{}", cursor::Show)?;
+    screen.flush()?;
+    Ok(picked)
+}
+
+fn draw(screen: &mut Screen, state: &State) -> io::Result<()> {
+    write!(screen, "{}{}", clear::All, cursor::Goto(1, 1))?;
+    match &state.notice {
+        Some(notice) => {
+            write!(screen, "{}{}{}\r\n\r\n", style::Bold, notice.title, style::Reset)?;
+            for line in notice.full_text().lines() {
+                write!(screen, "{}\r\n", line)?;
+            }
+        }
+        None => {
+            let breadcrumbs = state.breadcrumbs();
+            write!(screen, "{}{}{}\r\n\r\n", style::Bold, breadcrumbs, style::Reset)?;
+            for child in state.navigator.entries() {
+                write!(screen, "  {:>8}  {}\r\n", child.shortcut.to_string(), child.name)?;
+            }
+        }
+    }
+    screen.flush()
+}
+
+/**
+ * Open `file` at `line` in the editor on the main screen, then reload the
+ * config.
+ */
+fn edit(screen: &mut Screen, state: &mut State, file: &Path, line: Option<usize>) -> io::Result<()> {
+    screen.suspend_raw_mode()?;
+    write!(screen, "{}{}", screen::ToMainScreen, cursor::Show)?;
+    screen.flush()?;
+
+    let edited = editor::open_in_editor(file, line).and_then(|mut child| child.wait());
+
+    write!(screen, "{}{}", screen::ToAlternateScreen, cursor::Hide)?;
+    screen.activate_raw_mode()?;
+    match edited {
+        Ok(_) => {
+            let _ = state.reload_config(config::load_config(&state.options));
+        }
+        Err(err) => {
+            let message = format!("Could not open {}: {}", file.display(), err);
+            state.notice = Some(Notice::error(message));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * The press of a key in the terminal.
+ *
+ * Terminals send uppercase letters instead of Shift, and cannot tell
+ * combinations of several modifiers apart.
+ */
+fn key_press_for_key(key: Key) -> KeyPress {
+    let key_code = match key {
+        Key::Char(character) | Key::Ctrl(character) | Key::Alt(character) => {
+            let modifiers = Modifiers {
+                ctrl: matches!(key, Key::Ctrl(_)),
+                alt: matches!(key, Key::Alt(_)),
+                shift: character.is_uppercase(),
+                logo: false,
+            };
+            let key = KeyCode::from_char(character).map(|key_code| Shortcut {
+                key_code,
+                modifiers,
+            });
+            return KeyPress::typed(key, character);
+        }
+        Key::F(number) => KeyCode::F(number),
+        Key::Up => KeyCode::Up,
+        Key::Down => KeyCode::Down,
+        Key::Left => KeyCode::Left,
+        Key::Right => KeyCode::Right,
+        Key::Home => KeyCode::Home,
+        Key::End => KeyCode::End,
+        Key::PageUp => KeyCode::PageUp,
+        Key::PageDown => KeyCode::PageDown,
+        Key::Delete => KeyCode::Delete,
+        Key::Insert => KeyCode::Insert,
+        _ => return KeyPress::default(),
+    };
+    KeyPress::from(Shortcut::from(key_code))
+}
@@ -0,0 +1,498 @@
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde_json::Value;
+
+use crate::action::Action;
+use crate::bindings::{KeyCode, Matching, Shortcut, ShortcutPath};
+use crate::config::{self, ConfigError, IncludedCommands};
+use crate::config_format::Location;
+use crate::locations::{self, field_path, Locations};
+use crate::reopen::Then;
+use crate::Options;
+
+/**
+ * A problem found in a config file
+ */
+pub struct Diagnostic {
+    pub file: Option<PathBuf>,
+    pub location: Option<Location>,
+    /// Path of the offending value inside the file
+    pub path: Option<String>,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.file, self.location) {
+            (Some(file), Some(location)) => write!(f, "{}:{}: ", file.display(), location)?,
+            (Some(file), None) => write!(f, "{}: ", file.display())?,
+            (None, _) => {}
+        }
+        write!(f, "{}", self.message)?;
+        match (&self.path, self.location) {
+            (Some(path), None) if !path.is_empty() => write!(f, " (at `{}`)", path),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<ConfigError> for Diagnostic {
+    fn from(err: ConfigError) -> Self {
+        Diagnostic {
+            file: err.file().map(Path::to_owned),
+            location: err.location(),
+            path: None,
+            message: err.to_string(),
+        }
+    }
+}
+
+/**
+ * Check the config files and print every problem found.
+ *
+ * Returns the exit code, which is non-zero if there are problems.
+ */
+pub fn validate(options: &Options) -> i32 {
+    let diagnostics = check_config(options);
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+
+    if diagnostics.is_empty() {
+        println!("Config is valid.");
+        0
+    } else {
+        println!("{} problem(s) found.", diagnostics.len());
+        1
+    }
+}
+
+/**
+ * Find the problems of the config files and the files they include.
+ *
+ * Besides the errors that keep a config from loading this finds
+ * duplicate shortcuts among siblings, menus without entries and entries
+ * that can never be selected.
+ */
+pub fn check_config(options: &Options) -> Vec<Diagnostic> {
+    let files = match config::config_files(options) {
+        Ok(files) => files,
+        Err(err) => return vec![err.into()],
+    };
+
+    let mut validator = Validator {
+        diagnostics: vec![],
+        shortcut_matching: Matching::default(),
+    };
+    let files: Vec<Rc<ParsedFile>> = files
+        .iter()
+        .filter_map(|file| validator.parse_file(file))
+        .map(Rc::new)
+        .collect();
+    // The option of the highest layer applies to every file.
+    validator.shortcut_matching = files
+        .iter()
+        .rev()
+        .find_map(|file| file.value.get("shortcut_matching"))
+        .and_then(|matching| serde_json::from_value(matching.clone()).ok())
+        .unwrap_or_default();
+    for file in files {
+        validator.check_config_file(file);
+    }
+
+    // Catch whatever the checks above do not know about, e.g. invalid options.
+    if validator.diagnostics.is_empty() {
+        if let Err(err) = config::load_config(options) {
+            validator.diagnostics.extend(load_error_diagnostics(err));
+        }
+    }
+    let mut diagnostics = validator.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| {
+        (
+            diagnostic.file.clone(),
+            diagnostic.location.map(|location| (location.line, location.column)),
+        )
+    });
+    diagnostics
+}
+
+struct ParsedFile {
+    path: PathBuf,
+    value: Value,
+    locations: Locations,
+}
+
+/**
+ * A command to check, together with where it was found
+ */
+struct Entry {
+    file: Rc<ParsedFile>,
+    /// The files that included `file`, `file` being the last one
+    chain: Rc<Vec<PathBuf>>,
+    value: Value,
+    path: String,
+}
+
+struct Validator {
+    diagnostics: Vec<Diagnostic>,
+    /// How shortcuts of commands without a `shortcut_matching` are matched
+    shortcut_matching: Matching,
+}
+
+impl Validator {
+    fn report(&mut self, file: &ParsedFile, path: &str, message: String) {
+        self.diagnostics.push(Diagnostic {
+            file: Some(file.path.clone()),
+            location: locations::closest_location(&file.locations, path),
+            path: Some(path.to_owned()),
+            message,
+        });
+    }
+
+    fn parse_file(&mut self, path: &Path) -> Option<ParsedFile> {
+        let (format, contents) = match config::read_config_file(path) {
+            Ok(file) => file,
+            Err(err) => {
+                self.diagnostics.push(err.into());
+                return None;
+            }
+        };
+        match format.parse(&contents) {
+            Ok(value) => Some(ParsedFile {
+                path: path.to_owned(),
+                value,
+                locations: locations::locate(format, &contents),
+            }),
+            Err(err) => {
+                self.diagnostics.push(Diagnostic {
+                    file: Some(path.to_owned()),
+                    location: err.location,
+                    path: None,
+                    message: err.to_string(),
+                });
+                None
+            }
+        }
+    }
+
+    fn check_config_file(&mut self, file: Rc<ParsedFile>) {
+        match file.value.get("commands") {
+            Some(commands) => self.check_command(&Entry {
+                file: file.clone(),
+                chain: Rc::new(vec![config::canonical_path(&file.path)]),
+                value: commands.clone(),
+                path: "commands".into(),
+            }),
+            None => self.report(&file, "", "The config has no `commands`".into()),
+        }
+    }
+
+    fn check_command(&mut self, entry: &Entry) {
+        let command = match entry.value.as_object() {
+            Some(command) => command,
+            None => {
+                let message = "A command must be an object".into();
+                return self.report(&entry.file, &entry.path, message);
+            }
+        };
+
+        self.check_shortcut(entry);
+        if !command.contains_key("name") {
+            let message = "The command has no `name`".into();
+            self.report(&entry.file, &entry.path, message);
+        }
+
+        self.check_action(entry);
+        self.check_then(entry);
+
+        let children = command.get("children");
+        let include = command.get("include");
+        if children.is_none() && include.is_none() {
+            if !command.contains_key("cmd") && !command.contains_key("action") {
+                let message = "The command has neither `cmd`, `action` nor `children`".into();
+                self.report(&entry.file, &entry.path, message);
+            }
+            return;
+        }
+
+        let mut entries = vec![];
+        match children {
+            Some(Value::Array(children)) => {
+                let children_path = field_path(&entry.path, "children");
+                entries.extend(children_entries(entry, children, &children_path));
+            }
+            Some(_) => {
+                let message = "`children` must be a list of commands".into();
+                self.report(&entry.file, &field_path(&entry.path, "children"), message);
+            }
+            None => {}
+        }
+        let mut include_failed = false;
+        if let Some(include) = include {
+            match self.check_include(entry, include) {
+                Some(included_entries) => entries.extend(included_entries),
+                None => include_failed = true,
+            }
+        }
+
+        if entries.is_empty() && !include_failed {
+            let message = "The menu has no entries".into();
+            self.report(&entry.file, &entry.path, message);
+        }
+        self.check_siblings(&entries);
+        for entry in &entries {
+            self.check_command(entry);
+        }
+    }
+
+    fn check_shortcut(&mut self, entry: &Entry) {
+        let path = field_path(&entry.path, "shortcut");
+        let message = match entry.value.get("shortcut") {
+            None => "The command has no `shortcut`".into(),
+            Some(Value::String(shortcut)) => match shortcut.parse::<ShortcutPath>() {
+                Ok(ShortcutPath(shortcuts)) if shortcuts.is_empty() => {
+                    "The shortcut has no keys".into()
+                }
+                Ok(ShortcutPath(shortcuts)) => {
+                    match shortcuts.iter().find_map(|shortcut| self.unmatchable(entry, shortcut)) {
+                        Some(message) => message,
+                        None => return,
+                    }
+                }
+                Err(err) => format!("Invalid shortcut: {}", err),
+            },
+            Some(_) => "`shortcut` must be a string".into(),
+        };
+        self.report(&entry.file, &path, message);
+    }
+
+    fn check_action(&mut self, entry: &Entry) {
+        let action = match entry.value.get("action") {
+            Some(action) => action,
+            None => return,
+        };
+        let path = field_path(&entry.path, "action");
+        if entry.value.get("cmd").is_some() {
+            let message = "The command has both a `cmd` and an `action`".into();
+            return self.report(&entry.file, &path, message);
+        }
+        if let Err(err) = serde_json::from_value::<Action>(action.clone()) {
+            self.report(&entry.file, &path, format!("Invalid action: {}", err));
+        }
+    }
+
+    fn check_then(&mut self, entry: &Entry) {
+        let then = match entry.value.get("then") {
+            Some(then) => then,
+            None => return,
+        };
+        let path = field_path(&entry.path, "then");
+        if entry.value.get("output").and_then(Value::as_str) == Some("show") {
+            let message = "A command showing its output cannot have a `then`".into();
+            return self.report(&entry.file, &path, message);
+        }
+        if let Err(err) = serde_json::from_value::<Then>(then.clone()) {
+            self.report(&entry.file, &path, format!("Invalid `then`: {}", err));
+        }
+    }
+
+    /// Why `shortcut` can never be pressed, given how it is matched.
+    fn unmatchable(&self, entry: &Entry, shortcut: &Shortcut) -> Option<String> {
+        let matching = entry
+            .value
+            .get("shortcut_matching")
+            .and_then(|matching| serde_json::from_value(matching.clone()).ok())
+            .unwrap_or(self.shortcut_matching);
+        let by_character = matching == Matching::Character || !shortcut.key_code.is_key();
+        match shortcut.key_code {
+            KeyCode::Char(character)
+                if by_character && shortcut.modifiers.shift && !character.is_ascii_lowercase() =>
+            {
+                Some(format!(
+                    "`{}` never matches, Shift is part of the typed character",
+                    shortcut
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /**
+     * Load the entries of the file included by the command of `entry`.
+     *
+     * Returns `None` if the file could not be included.
+     */
+    fn check_include(&mut self, entry: &Entry, include: &Value) -> Option<Vec<Entry>> {
+        let include_value_path = field_path(&entry.path, "include");
+        let include = match include.as_str() {
+            Some(include) => include,
+            None => {
+                let message = "`include` must be a file path".into();
+                self.report(&entry.file, &include_value_path, message);
+                return None;
+            }
+        };
+
+        let path = match config::find_include(&entry.chain, include) {
+            Ok(path) => path,
+            Err(err) => {
+                self.report(&entry.file, &include_value_path, include_error_message(err));
+                return None;
+            }
+        };
+
+        let included = Rc::new(self.parse_file(&path)?);
+        let mut chain = (*entry.chain).clone();
+        chain.push(path);
+        let included_entry = Entry {
+            file: included.clone(),
+            chain: Rc::new(chain),
+            value: included.value.clone(),
+            path: String::new(),
+        };
+
+        match config::included_commands(&included.path, &included.value) {
+            Ok(IncludedCommands::List) => {
+                let items = included.value.as_array().map(Vec::as_slice).unwrap_or_default();
+                Some(children_entries(&included_entry, items, ""))
+            }
+            Ok(IncludedCommands::Node) => {
+                let mut entries = match included.value.get("children") {
+                    Some(Value::Array(children)) => {
+                        children_entries(&included_entry, children, "children")
+                    }
+                    _ => vec![],
+                };
+                if let Some(include) = included.value.get("include") {
+                    entries.extend(self.check_include(&included_entry, include)?);
+                }
+                Some(entries)
+            }
+            Err(err) => {
+                self.report(&included, "", include_error_message(err));
+                None
+            }
+        }
+    }
+
+    /**
+     * Report shortcuts bound more than once, only the first one is reachable.
+     *
+     * A key sequence starting with the shortcut of a sibling is unreachable
+     * as well, as the sibling is selected as soon as its keys are pressed.
+     */
+    fn check_siblings(&mut self, entries: &[Entry]) {
+        let mut bound: Vec<(ShortcutPath, &Entry)> = vec![];
+        for entry in entries {
+            let shortcut = entry
+                .value
+                .get("shortcut")
+                .and_then(Value::as_str)
+                .and_then(|shortcut| shortcut.parse::<ShortcutPath>().ok())
+                .filter(|shortcut| !shortcut.0.is_empty());
+            let shortcut = match shortcut {
+                Some(shortcut) => shortcut,
+                None => continue,
+            };
+
+            let conflict = bound.iter().find(|(bound_shortcut, _)| {
+                shortcut.0.starts_with(&bound_shortcut.0) || bound_shortcut.0.starts_with(&shortcut.0)
+            });
+            let message = match conflict {
+                Some((bound_shortcut, first)) => {
+                    let name = first.value.get("name").and_then(Value::as_str).unwrap_or("");
+                    if *bound_shortcut == shortcut {
+                        format!(
+                            "`{}` is already bound to `{}` at {}, this entry is unreachable",
+                            shortcut,
+                            name,
+                            describe_position(first)
+                        )
+                    } else if shortcut.0.starts_with(&bound_shortcut.0) {
+                        format!(
+                            "`{}` starts with `{}`, which is bound to `{}` at {}, \
+                             this entry is unreachable",
+                            shortcut,
+                            bound_shortcut,
+                            name,
+                            describe_position(first)
+                        )
+                    } else {
+                        format!(
+                            "`{}` is the start of `{}`, which is bound to `{}` at {} \
+                             and unreachable because of this entry",
+                            shortcut,
+                            bound_shortcut,
+                            name,
+                            describe_position(first)
+                        )
+                    }
+                }
+                None => {
+                    bound.push((shortcut, entry));
+                    continue;
+                }
+            };
+            self.report(&entry.file, &field_path(&entry.path, "shortcut"), message);
+        }
+    }
+}
+
+/// The message of an error including a file, the diagnostic names the file already.
+fn include_error_message(err: ConfigError) -> String {
+    match err {
+        ConfigError::Io(path, err) => format!("Could not include {}: {}", path.display(), err),
+        ConfigError::InvalidInclude(_, reason) => format!("Invalid include: {}", reason),
+        err => err.to_string(),
+    }
+}
+
+/// Diagnostics for an error of loading the config, located where possible.
+fn load_error_diagnostics(err: ConfigError) -> Vec<Diagnostic> {
+    match err {
+        ConfigError::Include(_, err) => load_error_diagnostics(*err),
+        ConfigError::Invalid(file, invalid) => {
+            vec![located_diagnostic(file, &invalid.path, invalid.message)]
+        }
+        ConfigError::UnknownFields(file, fields) => fields
+            .iter()
+            .map(|field| {
+                let message = format!("Unknown field `{}`", field);
+                located_diagnostic(file.clone(), field, message)
+            })
+            .collect(),
+        err => vec![err.into()],
+    }
+}
+
+fn located_diagnostic(file: PathBuf, path: &str, message: String) -> Diagnostic {
+    let location = locations::locate_in_file(&file, path);
+    Diagnostic {
+        file: Some(file),
+        location,
+        path: Some(path.to_owned()),
+        message,
+    }
+}
+
+fn children_entries(parent: &Entry, children: &[Value], children_path: &str) -> Vec<Entry> {
+    children
+        .iter()
+        .enumerate()
+        .map(|(index, child)| Entry {
+            file: parent.file.clone(),
+            chain: parent.chain.clone(),
+            value: child.clone(),
+            path: format!("{}[{}]", children_path, index),
+        })
+        .collect()
+}
+
+fn describe_position(entry: &Entry) -> String {
+    match locations::closest_location(&entry.file.locations, &entry.path) {
+        Some(location) => format!("{}:{}", entry.file.path.display(), location),
+        None => format!("{} `{}`", entry.file.path.display(), entry.path),
+    }
+}
@@ -1,13 +1,10 @@
-use std::process::Command as CliCommand;
-
 use conrod::backend::glium::glium;
 use conrod::backend::glium::glium::backend::glutin::glutin::Event;
 use conrod::backend::glium::glium::backend::glutin::Display;
 use conrod::Ui;
 use conrod::{color, widget_ids};
 
-use crate::bindings::Shortcut;
-use crate::commands::Command;
+use crate::bindings::{KeyCode, Modifiers, Shortcut};
 use crate::state::State;
 use crate::window_position::WindowPosition;
 
@@ -23,21 +20,37 @@ widget_ids! {
         command_list_item_name_canvas[],
         command_list_item_shortcut_widget[],
         command_list_item_name_widget[],
+        notice_text,
+        output_text,
+        output_scrollbar,
     }
 }
 
 pub enum SpacerunEvent {
-    SelectCommand(Command),
+    /// A key was pressed, the character it typed follows as `CharacterTyped`
+    KeyPressed(Shortcut),
+    CharacterTyped(char),
     PrevLevelCommand,
+    DismissNotice,
+    /// Open the file of the shown notice in an editor
+    EditNoticeFile,
+    /// Go back from the output of a command to the commands
+    DismissOutput,
+    /// Scroll the output of a command by the distance, down if positive
+    ScrollOutput(f64),
     FocusLost,
     CloseApplication,
 }
 
 static DEFAULT_FONT_SIZE: u32 = 14;
+static NOTICE_MARGIN: f64 = 10.0;
+/// The window grows with the output of a command up to this height.
+static MAX_OUTPUT_HEIGHT: f64 = 500.0;
+static OUTPUT_SCROLL_PAGE_LINES: f64 = 10.0;
 
 pub fn handle_event(event: &Event, state: &State) -> Option<SpacerunEvent> {
-    match event {
-        glium::glutin::Event::WindowEvent { event, .. } => match event {
+    if let glium::glutin::Event::WindowEvent { event, .. } = event {
+        match event {
             // Break from the loop upon `Escape`.
             glium::glutin::WindowEvent::CloseRequested
             | glium::glutin::WindowEvent::KeyboardInput {
@@ -52,41 +65,180 @@ pub fn handle_event(event: &Event, state: &State) -> Option<SpacerunEvent> {
             glium::glutin::WindowEvent::KeyboardInput { input, .. } => {
                 if let Some(virtual_keycode) = input.virtual_keycode {
                     if input.state == glium::glutin::ElementState::Pressed {
+                        if let Some(notice) = &state.notice {
+                            return match virtual_keycode {
+                                glium::glutin::VirtualKeyCode::E if notice.file.is_some() => {
+                                    Some(SpacerunEvent::EditNoticeFile)
+                                }
+                                glium::glutin::VirtualKeyCode::Back => {
+                                    Some(SpacerunEvent::DismissNotice)
+                                }
+                                _ => None,
+                            };
+                        }
+                        if state.output.is_some() {
+                            let line = output_line_height(state);
+                            return match virtual_keycode {
+                                glium::glutin::VirtualKeyCode::Back => {
+                                    Some(SpacerunEvent::DismissOutput)
+                                }
+                                glium::glutin::VirtualKeyCode::Up => {
+                                    Some(SpacerunEvent::ScrollOutput(-line))
+                                }
+                                glium::glutin::VirtualKeyCode::Down => {
+                                    Some(SpacerunEvent::ScrollOutput(line))
+                                }
+                                glium::glutin::VirtualKeyCode::PageUp => Some(
+                                    SpacerunEvent::ScrollOutput(-line * OUTPUT_SCROLL_PAGE_LINES),
+                                ),
+                                glium::glutin::VirtualKeyCode::PageDown => Some(
+                                    SpacerunEvent::ScrollOutput(line * OUTPUT_SCROLL_PAGE_LINES),
+                                ),
+                                _ => None,
+                            };
+                        }
                         if virtual_keycode == glium::glutin::VirtualKeyCode::Back {
                             return Some(SpacerunEvent::PrevLevelCommand);
                         }
-                        let pressed_shortcut = Shortcut {
-                            modifiers: input.modifiers,
-                            key_code: virtual_keycode.into(),
-                        };
-                        let found_child = state
-                            .selected_command
-                            .find_child_for_shortcut(&pressed_shortcut);
-                        if let Some(found_child) = found_child {
-                            return select_command(&found_child);
+                        if let Some(pressed_shortcut) =
+                            shortcut_for_key(virtual_keycode, input.modifiers)
+                        {
+                            return Some(SpacerunEvent::KeyPressed(pressed_shortcut));
                         }
                     }
                 }
             }
+            // Keys like Backspace and Ctrl combinations type control characters,
+            // they are handled as the key pressed
+            glium::glutin::WindowEvent::ReceivedCharacter(character)
+                if !character.is_control() && state.notice.is_none() && state.output.is_none() =>
+            {
+                return Some(SpacerunEvent::CharacterTyped(*character));
+            }
             _ => (),
-        },
-        _ => (),
+        }
     }
     None
 }
 
-fn select_command(command: & Command) -> Option<SpacerunEvent> {
-    match command {
-        command @ Command::Node(_) => return Some(SpacerunEvent::SelectCommand(command.clone())),
-        Command::Leaf(child_leaf) => {
-            CliCommand::new("sh")
-                .arg("-c")
-                .arg(&child_leaf.cmd)
-                .spawn()
-                .expect("process failed to execute");
-            return Some(SpacerunEvent::CloseApplication);
-        }
-    }
+/// The shortcut of a key pressed in the window, if spacerun knows the key.
+fn shortcut_for_key(
+    key: glium::glutin::VirtualKeyCode,
+    modifiers: glium::glutin::ModifiersState,
+) -> Option<Shortcut> {
+    use glium::glutin::VirtualKeyCode;
+
+    let key_code = match key {
+        VirtualKeyCode::A => KeyCode::Char('a'),
+        VirtualKeyCode::B => KeyCode::Char('b'),
+        VirtualKeyCode::C => KeyCode::Char('c'),
+        VirtualKeyCode::D => KeyCode::Char('d'),
+        VirtualKeyCode::E => KeyCode::Char('e'),
+        VirtualKeyCode::F => KeyCode::Char('f'),
+        VirtualKeyCode::G => KeyCode::Char('g'),
+        VirtualKeyCode::H => KeyCode::Char('h'),
+        VirtualKeyCode::I => KeyCode::Char('i'),
+        VirtualKeyCode::J => KeyCode::Char('j'),
+        VirtualKeyCode::K => KeyCode::Char('k'),
+        VirtualKeyCode::L => KeyCode::Char('l'),
+        VirtualKeyCode::M => KeyCode::Char('m'),
+        VirtualKeyCode::N => KeyCode::Char('n'),
+        VirtualKeyCode::O => KeyCode::Char('o'),
+        VirtualKeyCode::P => KeyCode::Char('p'),
+        VirtualKeyCode::Q => KeyCode::Char('q'),
+        VirtualKeyCode::R => KeyCode::Char('r'),
+        VirtualKeyCode::S => KeyCode::Char('s'),
+        VirtualKeyCode::T => KeyCode::Char('t'),
+        VirtualKeyCode::U => KeyCode::Char('u'),
+        VirtualKeyCode::V => KeyCode::Char('v'),
+        VirtualKeyCode::W => KeyCode::Char('w'),
+        VirtualKeyCode::X => KeyCode::Char('x'),
+        VirtualKeyCode::Y => KeyCode::Char('y'),
+        VirtualKeyCode::Z => KeyCode::Char('z'),
+        VirtualKeyCode::Key1 => KeyCode::Char('1'),
+        VirtualKeyCode::Key2 => KeyCode::Char('2'),
+        VirtualKeyCode::Key3 => KeyCode::Char('3'),
+        VirtualKeyCode::Key4 => KeyCode::Char('4'),
+        VirtualKeyCode::Key5 => KeyCode::Char('5'),
+        VirtualKeyCode::Key6 => KeyCode::Char('6'),
+        VirtualKeyCode::Key7 => KeyCode::Char('7'),
+        VirtualKeyCode::Key8 => KeyCode::Char('8'),
+        VirtualKeyCode::Key9 => KeyCode::Char('9'),
+        VirtualKeyCode::Key0 => KeyCode::Char('0'),
+        VirtualKeyCode::Comma => KeyCode::Char(','),
+        VirtualKeyCode::Period => KeyCode::Char('.'),
+        VirtualKeyCode::Slash => KeyCode::Char('/'),
+        VirtualKeyCode::Semicolon => KeyCode::Char(';'),
+        VirtualKeyCode::Apostrophe => KeyCode::Char('\''),
+        VirtualKeyCode::LBracket => KeyCode::Char('['),
+        VirtualKeyCode::RBracket => KeyCode::Char(']'),
+        VirtualKeyCode::Minus => KeyCode::Char('-'),
+        VirtualKeyCode::Equals => KeyCode::Char('='),
+        VirtualKeyCode::Grave => KeyCode::Char('`'),
+        VirtualKeyCode::Space => KeyCode::Space,
+        VirtualKeyCode::Return => KeyCode::Return,
+        VirtualKeyCode::Tab => KeyCode::Tab,
+        VirtualKeyCode::Delete => KeyCode::Delete,
+        VirtualKeyCode::Insert => KeyCode::Insert,
+        VirtualKeyCode::Home => KeyCode::Home,
+        VirtualKeyCode::End => KeyCode::End,
+        VirtualKeyCode::PageUp => KeyCode::PageUp,
+        VirtualKeyCode::PageDown => KeyCode::PageDown,
+        VirtualKeyCode::Up => KeyCode::Up,
+        VirtualKeyCode::Down => KeyCode::Down,
+        VirtualKeyCode::Left => KeyCode::Left,
+        VirtualKeyCode::Right => KeyCode::Right,
+        // winit knows no function keys above F15
+        VirtualKeyCode::F1 => KeyCode::F(1),
+        VirtualKeyCode::F2 => KeyCode::F(2),
+        VirtualKeyCode::F3 => KeyCode::F(3),
+        VirtualKeyCode::F4 => KeyCode::F(4),
+        VirtualKeyCode::F5 => KeyCode::F(5),
+        VirtualKeyCode::F6 => KeyCode::F(6),
+        VirtualKeyCode::F7 => KeyCode::F(7),
+        VirtualKeyCode::F8 => KeyCode::F(8),
+        VirtualKeyCode::F9 => KeyCode::F(9),
+        VirtualKeyCode::F10 => KeyCode::F(10),
+        VirtualKeyCode::F11 => KeyCode::F(11),
+        VirtualKeyCode::F12 => KeyCode::F(12),
+        VirtualKeyCode::F13 => KeyCode::F(13),
+        VirtualKeyCode::F14 => KeyCode::F(14),
+        VirtualKeyCode::F15 => KeyCode::F(15),
+        VirtualKeyCode::Numpad0 => KeyCode::Numpad(0),
+        VirtualKeyCode::Numpad1 => KeyCode::Numpad(1),
+        VirtualKeyCode::Numpad2 => KeyCode::Numpad(2),
+        VirtualKeyCode::Numpad3 => KeyCode::Numpad(3),
+        VirtualKeyCode::Numpad4 => KeyCode::Numpad(4),
+        VirtualKeyCode::Numpad5 => KeyCode::Numpad(5),
+        VirtualKeyCode::Numpad6 => KeyCode::Numpad(6),
+        VirtualKeyCode::Numpad7 => KeyCode::Numpad(7),
+        VirtualKeyCode::Numpad8 => KeyCode::Numpad(8),
+        VirtualKeyCode::Numpad9 => KeyCode::Numpad(9),
+        VirtualKeyCode::Add => KeyCode::NumpadAdd,
+        VirtualKeyCode::Subtract => KeyCode::NumpadSubtract,
+        VirtualKeyCode::Multiply => KeyCode::NumpadMultiply,
+        VirtualKeyCode::Divide => KeyCode::NumpadDivide,
+        VirtualKeyCode::Decimal => KeyCode::NumpadDecimal,
+        VirtualKeyCode::NumpadEnter => KeyCode::NumpadEnter,
+        VirtualKeyCode::NumpadEquals => KeyCode::NumpadEquals,
+        VirtualKeyCode::PlayPause => KeyCode::PlayPause,
+        VirtualKeyCode::MediaStop => KeyCode::MediaStop,
+        VirtualKeyCode::NextTrack => KeyCode::NextTrack,
+        VirtualKeyCode::PrevTrack => KeyCode::PrevTrack,
+        VirtualKeyCode::VolumeUp => KeyCode::VolumeUp,
+        VirtualKeyCode::VolumeDown => KeyCode::VolumeDown,
+        VirtualKeyCode::Mute => KeyCode::Mute,
+        _ => return None,
+    };
+    Some(Shortcut {
+        key_code,
+        modifiers: Modifiers {
+            ctrl: modifiers.ctrl,
+            alt: modifiers.alt,
+            shift: modifiers.shift,
+            logo: modifiers.logo,
+        },
+    })
 }
 
 /**
@@ -99,10 +251,20 @@ fn select_command(command: & Command) -> Option<SpacerunEvent> {
  *
  * TODO (LinuCC) We probably need a max height? Same as window height?
  */
-pub fn rendered_elements_height(ui: &Ui, ids: &Ids) -> Option<f64> {
-    if let Some(list_render_rect) = ui.kids_bounding_box(ids.command_list) {
+pub fn rendered_elements_height(ui: &Ui, ids: &Ids, state: &State) -> Option<f64> {
+    let body_height = if state.notice.is_some() {
+        ui.rect_of(ids.notice_text)
+            .map(|notice_rect| notice_rect.h() + 2.0 * NOTICE_MARGIN)
+    } else if state.output.is_some() {
+        ui.rect_of(ids.output_text)
+            .map(|output_rect| (output_rect.h() + 2.0 * NOTICE_MARGIN).min(MAX_OUTPUT_HEIGHT))
+    } else {
+        ui.kids_bounding_box(ids.command_list)
+            .map(|list_render_rect| list_render_rect.h())
+    };
+    if let Some(body_height) = body_height {
         if let Some(head_render_rect) = ui.kids_bounding_box(ids.head_canvas) {
-            return Some(list_render_rect.h() + head_render_rect.h());
+            return Some(body_height + head_render_rect.h());
         }
     }
     None
@@ -114,10 +276,10 @@ pub fn update_initial_window_state(ui: &mut Ui, state: &mut State, ids: &mut Ids
     // FIXME LinuCC For some reason `ui.kids_bounding_box()` accesses the
     //     `ui.prev_updated_widgets`, which only exists after generating the Ui
     //     a second time.
-    set_ui(ui.set_widgets(), &state, &state.selected_command, ids);
-    set_ui(ui.set_widgets(), &state, &state.selected_command, ids);
+    set_ui(ui.set_widgets(), state, ids);
+    set_ui(ui.set_widgets(), state, ids);
 
-    if let Some(height) = rendered_elements_height(ui, ids) {
+    if let Some(height) = rendered_elements_height(ui, ids, state) {
         state.window_dimensions.height = height;
     }
 }
@@ -127,7 +289,7 @@ pub fn update_window_and_window_state(
     state: &mut State,
     display: &Display,
     force_update: bool,
-) -> () {
+) {
     if new_window_height != state.window_dimensions.height || force_update {
         eprintln!("Updating window size.");
 
@@ -142,7 +304,7 @@ pub fn update_window_and_window_state(
                 let current_monitor = display.gl_window().get_current_monitor();
                 state.window_dimensions.width = current_monitor.get_dimensions().width;
                 let monitor_height = current_monitor.get_dimensions().height;
-                new_window_position = Some((0.0, monitor_height - new_window_height as f64).into());
+                new_window_position = Some((0.0, monitor_height - new_window_height).into());
             }
             _ => {}
         };
@@ -158,11 +320,11 @@ pub fn update_window_and_window_state(
 }
 
 // Declare the `WidgetId`s and instantiate the widgets.
-pub fn set_ui(ref mut ui: conrod::UiCell, state: &State, command: &Command, ids: &mut Ids) {
+pub fn set_ui(mut ui: conrod::UiCell, state: &State, ids: &mut Ids) {
     use conrod::{widget, Colorable, Positionable, Sizeable, Widget};
+    let ui = &mut ui;
 
-
-    let displayed_leafs = command.displayable_children();
+    let displayed_leafs = state.navigator.entries();
 
     // Make sure we have enough Ids for the displayed items
     if displayed_leafs.len() != ids.command_list_item_canvas.len() {
@@ -189,8 +351,14 @@ pub fn set_ui(ref mut ui: conrod::UiCell, state: &State, command: &Command, ids:
         ),
         (
             ids.list_canvas,
-            widget::Canvas::new()
-                .color(color::BLUE),
+            if state.output.is_some() {
+                widget::Canvas::new()
+                    .color(color::BLUE)
+                    .scroll_kids_vertically()
+            } else {
+                widget::Canvas::new()
+                    .color(color::BLUE)
+            },
         ),
     ];
     // let canvas = widget::Canvas::new()
@@ -199,9 +367,11 @@ pub fn set_ui(ref mut ui: conrod::UiCell, state: &State, command: &Command, ids:
         .flow_down(&child_canvas)
         .set(ids.canvas, ui);
 
-    let breadcrumb_text = state.selection_path.iter().fold("Root".into(), |acc, selection| {
-        format!("{} > {}", acc, selection.name)
-    });
+    let breadcrumb_text = match (&state.notice, &state.output) {
+        (Some(notice), _) => notice.title.clone(),
+        (None, Some(output)) => output.title(),
+        (None, None) => state.breadcrumbs(),
+    };
     widget::Text::new(&breadcrumb_text)
         .mid_left_of(ids.head_canvas)
         .color(color::WHITE)
@@ -209,6 +379,30 @@ pub fn set_ui(ref mut ui: conrod::UiCell, state: &State, command: &Command, ids:
         .font_size(state.config.font_size.unwrap_or(DEFAULT_FONT_SIZE))
         .set(ids.head_breadcrumbs, ui);
 
+    if let Some(notice) = &state.notice {
+        widget::Text::new(&notice.full_text())
+            .top_left_with_margins_on(ids.list_canvas, NOTICE_MARGIN, NOTICE_MARGIN)
+            .padded_w_of(ids.list_canvas, NOTICE_MARGIN)
+            .wrap_by_word()
+            .color(color::WHITE)
+            .font_size(state.config.font_size.unwrap_or(DEFAULT_FONT_SIZE))
+            .set(ids.notice_text, ui);
+        return;
+    }
+
+    if let Some(output) = &state.output {
+        widget::Text::new(&output.full_text())
+            .top_left_with_margins_on(ids.list_canvas, NOTICE_MARGIN, NOTICE_MARGIN)
+            .padded_w_of(ids.list_canvas, NOTICE_MARGIN)
+            .wrap_by_character()
+            .color(color::WHITE)
+            .font_size(state.config.font_size.unwrap_or(DEFAULT_FONT_SIZE))
+            .set(ids.output_text, ui);
+        widget::Scrollbar::y_axis(ids.list_canvas)
+            .auto_hide(true)
+            .set(ids.output_scrollbar, ui);
+        return;
+    }
 
     // Generate list displaying the commands
     let (mut items, scrollbar) = widget::List::flow_down(displayed_leafs.len())
@@ -229,10 +423,7 @@ pub fn set_ui(ref mut ui: conrod::UiCell, state: &State, command: &Command, ids:
         let child_canvas = [
             (
                 ids.command_list_item_shortcut_canvas[i],
-                text_container_canvas
-                    .clone()
-                    .length_weight(0.2)
-                    .color(color::ORANGE),
+                text_container_canvas.length_weight(0.2).color(color::ORANGE),
             ),
             (
                 ids.command_list_item_name_canvas[i],
@@ -261,6 +452,11 @@ pub fn set_ui(ref mut ui: conrod::UiCell, state: &State, command: &Command, ids:
     }
 }
 
+/// The distance scrolling the output by one line moves it.
+fn output_line_height(state: &State) -> f64 {
+    f64::from(state.config.font_size.unwrap_or(DEFAULT_FONT_SIZE)) * 1.5
+}
+
 /// Calculate the items height by the given font size
 fn item_height_by_font_size(font_size: u32) -> u32 {
     font_size + 20
@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use conrod::backend::glium::glium::glutin::EventsLoopProxy;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::config::canonical_path;
+
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(200);
+
+/**
+ * Watches the config files and the files they include for changes.
+ *
+ * The directories of the files are watched instead of the files themselves,
+ * as many editors replace a file on saving instead of writing to it. A
+ * directory that does not exist yet is waited for by watching the nearest one
+ * that does.
+ */
+pub struct ConfigWatcher {
+    watcher: RecommendedWatcher,
+    /// Paths that changed in the watched directories
+    changes: Receiver<PathBuf>,
+    watched_dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+impl ConfigWatcher {
+    /// Create a watcher waking up the events loop of `proxy` on changes.
+    pub fn new(proxy: EventsLoopProxy) -> notify::Result<ConfigWatcher> {
+        let (event_sender, events) = mpsc::channel();
+        let watcher = notify::watcher(event_sender, DEBOUNCE_DELAY)?;
+
+        let (change_sender, changes) = mpsc::channel();
+        thread::spawn(move || {
+            for event in events {
+                let path = match event {
+                    DebouncedEvent::Create(path)
+                    | DebouncedEvent::Write(path)
+                    | DebouncedEvent::Remove(path)
+                    | DebouncedEvent::Rename(_, path) => path,
+                    _ => continue,
+                };
+                if change_sender.send(path).is_err() || proxy.wakeup().is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(ConfigWatcher {
+            watcher,
+            changes,
+            watched_dirs: vec![],
+            files: vec![],
+        })
+    }
+
+    /// Watch `files` instead of the files watched so far.
+    pub fn watch(&mut self, files: Vec<PathBuf>) {
+        // Paths of events start with the watched directory, so both have to
+        // be canonical to compare them.
+        let files: Vec<PathBuf> = files.iter().filter_map(|file| canonical_file(file)).collect();
+        let mut dirs: Vec<PathBuf> = files
+            .iter()
+            .filter_map(|file| existing_dir(file.parent()?))
+            .map(ToOwned::to_owned)
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+
+        for dir in &self.watched_dirs {
+            if !dirs.contains(dir) {
+                let _ = self.watcher.unwatch(dir);
+            }
+        }
+        for dir in &dirs {
+            if !self.watched_dirs.contains(dir) {
+                if let Err(err) = self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    eprintln!("Could not watch {}: {}", dir.display(), err);
+                }
+            }
+        }
+        self.watched_dirs = dirs;
+        self.files = files;
+    }
+
+    /**
+     * Whether a watched file changed since the last call.
+     *
+     * A directory created on the way to a watched file counts as a change,
+     * the files have to be watched again to watch it.
+     */
+    pub fn files_changed(&self) -> bool {
+        self.changes
+            .try_iter()
+            .filter(|path| self.files.iter().any(|file| file.starts_with(path)))
+            .count()
+            > 0
+    }
+}
+
+/// The nearest directory of `path` that exists, `path` itself if it does.
+fn existing_dir(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|dir| dir.is_dir())
+}
+
+/// `file` with the part of its path that exists made canonical.
+fn canonical_file(file: &Path) -> Option<PathBuf> {
+    let dir = existing_dir(file.parent()?)?;
+    let rest = file.strip_prefix(dir).ok()?;
+    Some(canonical_path(dir).join(rest))
+}